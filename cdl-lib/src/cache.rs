@@ -0,0 +1,268 @@
+use crate::models::{ModInfo, ProviderKind};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A folder of downloaded files shared between instances, laid out as
+/// `<provider>/<project ID>/<file ID>/<SHA-256>/<file name>`.
+#[derive(Debug)]
+pub struct Cache {
+    pub dir: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct CachedFile {
+    pub provider: ProviderKind,
+    pub project_id: u64,
+    pub file_id: u64,
+    pub sha256: String,
+    pub file_name: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+impl Cache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Opens the cache in `$XDG_CACHE_HOME/cdl`, or `~/.cache/cdl` if that
+    /// is not set. Returns `None` if neither variable is set.
+    pub fn open() -> Option<Self> {
+        let dir = match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => Path::new(&env::var_os("HOME")?).join(".cache"),
+        };
+        Some(Self::new(dir.join("cdl")))
+    }
+
+    /// Finds a cached copy of the given file whose contents still match
+    /// its hash, removing any that do not.
+    pub fn get(&self, info: &ModInfo) -> Option<PathBuf> {
+        let files = self
+            .files_of(info.provider, info.project_id, info.id)
+            .ok()?;
+        for file in files {
+            match crate::file_hash(&file.path) {
+                Ok(hash) if hash == file.sha256 => return Some(file.path),
+                _ => {
+                    let _ = fs::remove_dir_all(file.path.parent()?);
+                }
+            }
+        }
+        None
+    }
+
+    /// Adds the file at `path`, which is `info`, to the cache unless it is
+    /// already there.
+    pub fn insert(&self, info: &ModInfo, path: &Path) -> io::Result<PathBuf> {
+        let sha256 = crate::file_hash(path)?;
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+
+        let dir = self
+            .file_dir(info.provider, info.project_id, info.id)
+            .join(sha256);
+        let cached = dir.join(file_name);
+        if !cached.exists() {
+            fs::create_dir_all(&dir)?;
+            link(path, &cached)?;
+        }
+
+        Ok(cached)
+    }
+
+    /// Every file in the cache, ordered by project and file ID.
+    pub fn list(&self) -> io::Result<Vec<CachedFile>> {
+        let mut files = vec![];
+        for provider in providers(&self.dir)? {
+            let provider_dir = self.dir.join(provider_dir(provider));
+            for project_id in ids(&provider_dir)? {
+                for file_id in ids(&provider_dir.join(project_id.to_string()))? {
+                    files.extend(self.files_of(provider, project_id, file_id)?);
+                }
+            }
+        }
+
+        files.sort_by_key(|f| (provider_dir(f.provider), f.project_id, f.file_id));
+        Ok(files)
+    }
+
+    /// Removes everything in the cache, returning the number of files removed.
+    pub fn clean(&self) -> io::Result<usize> {
+        let count = self.list()?.len();
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(count)
+    }
+
+    /// Removes every file but the newest of each project, returning the
+    /// removed files.
+    pub fn prune(&self) -> io::Result<Vec<CachedFile>> {
+        let files = self.list()?;
+
+        let mut newest = HashMap::new();
+        for file in &files {
+            let id = newest
+                .entry((file.provider, file.project_id))
+                .or_insert(file.file_id);
+            *id = (*id).max(file.file_id);
+        }
+
+        let mut removed = vec![];
+        for file in files {
+            if newest[&(file.provider, file.project_id)] == file.file_id {
+                continue;
+            }
+
+            let file_dir = self.file_dir(file.provider, file.project_id, file.file_id);
+            if file_dir.exists() {
+                fs::remove_dir_all(file_dir)?;
+            }
+            removed.push(file);
+        }
+
+        Ok(removed)
+    }
+
+    fn file_dir(&self, provider: ProviderKind, project_id: u64, file_id: u64) -> PathBuf {
+        self.dir
+            .join(provider_dir(provider))
+            .join(project_id.to_string())
+            .join(file_id.to_string())
+    }
+
+    fn files_of(
+        &self,
+        provider: ProviderKind,
+        project_id: u64,
+        file_id: u64,
+    ) -> io::Result<Vec<CachedFile>> {
+        let dir = self.file_dir(provider, project_id, file_id);
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut files = vec![];
+        for hash_dir in fs::read_dir(dir)? {
+            let hash_dir = hash_dir?;
+            if !hash_dir.file_type()?.is_dir() {
+                continue;
+            }
+
+            for file in fs::read_dir(hash_dir.path())? {
+                let file = file?;
+                let metadata = file.metadata()?;
+                if !metadata.is_file() {
+                    continue;
+                }
+
+                files.push(CachedFile {
+                    provider,
+                    project_id,
+                    file_id,
+                    sha256: hash_dir.file_name().to_string_lossy().into(),
+                    file_name: file.file_name().to_string_lossy().into(),
+                    path: file.path(),
+                    size: metadata.len(),
+                });
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// Hard links `from` to `to`, or copies it if that is not possible, such
+/// as when the two are on different file systems. Anything already at `to`
+/// is replaced.
+pub fn link(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        fs::remove_file(to)?;
+    }
+
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
+
+fn provider_dir(provider: ProviderKind) -> String {
+    provider.to_string().to_lowercase()
+}
+
+/// The providers that have a folder in `dir`.
+fn providers(dir: &Path) -> io::Result<Vec<ProviderKind>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut providers = vec![];
+    for entry in fs::read_dir(dir)? {
+        if let Some(provider) = entry?.file_name().to_str().and_then(|s| s.parse().ok()) {
+            providers.push(provider);
+        }
+    }
+    Ok(providers)
+}
+
+/// The names of the subfolders of `dir` that are IDs.
+fn ids(dir: &Path) -> io::Result<Vec<u64>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut ids = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if let Some(id) = entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::info;
+
+    #[test]
+    fn prune() {
+        let dir = env::temp_dir().join(format!("cdl-cache-{}", std::process::id()));
+        let cache = Cache::new(dir.join("cache"));
+        fs::create_dir_all(&dir).unwrap();
+
+        for &(project_id, file_id) in &[(1, 10), (1, 11), (2, 20)] {
+            let path = dir.join(format!("{}.jar", file_id));
+            fs::write(&path, file_id.to_string()).unwrap();
+            cache.insert(&info(project_id, file_id), &path).unwrap();
+        }
+        assert!(cache.get(&info(1, 10)).is_some());
+
+        let removed = cache.prune().unwrap();
+        assert_eq!(
+            removed.iter().map(|f| f.file_id).collect::<Vec<_>>(),
+            vec![10]
+        );
+        assert!(cache.get(&info(1, 10)).is_none());
+        assert_eq!(
+            cache
+                .list()
+                .unwrap()
+                .iter()
+                .map(|f| f.file_id)
+                .collect::<Vec<_>>(),
+            vec![11, 20]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
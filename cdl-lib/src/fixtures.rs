@@ -0,0 +1,62 @@
+//! Mods and files for tests to build on, all on CurseForge and named after
+//! their IDs.
+
+use crate::instance::LockedMod;
+use crate::models::{
+    Dependency, DependencyType, FileHashes, ModInfo, ModLoader, ProjectRef, ProviderKind,
+    ReleaseType,
+};
+
+pub fn refs(ids: &[u64]) -> Vec<ProjectRef> {
+    ids.iter()
+        .map(|&id| ProjectRef::new(ProviderKind::CurseForge, id))
+        .collect()
+}
+
+pub fn dependencies(ids: &[u64], dep_type: DependencyType) -> Vec<Dependency> {
+    ids.iter()
+        .map(|&addon_id| Dependency {
+            provider: ProviderKind::CurseForge,
+            addon_id,
+            dep_type,
+        })
+        .collect()
+}
+
+/// A file without dependencies.
+pub fn info(project_id: u64, id: u64) -> ModInfo {
+    ModInfo {
+        project_id,
+        provider: ProviderKind::CurseForge,
+        id,
+        display_name: project_id.to_string(),
+        file_name: format!("{}.jar", id),
+        file_length: 0,
+        package_fingerprint: 0,
+        hashes: FileHashes::default(),
+        release_type: ReleaseType::default(),
+        file_date: String::new(),
+        download_url: String::new(),
+        dependencies: vec![],
+    }
+}
+
+/// An installed mod, whose file ID is ten times its project ID.
+pub fn locked(project_id: u64, explicit: bool, dependencies: &[u64]) -> LockedMod {
+    LockedMod {
+        provider: ProviderKind::CurseForge,
+        project_id,
+        file_id: project_id * 10,
+        name: project_id.to_string(),
+        file_name: format!("{}.jar", project_id * 10),
+        download_url: String::new(),
+        sha256: String::new(),
+        file_date: String::new(),
+        game_version: "1.16.4".into(),
+        mod_loader: ModLoader::Forge,
+        explicit,
+        pinned: false,
+        dependencies: refs(dependencies),
+        incompatible: vec![],
+    }
+}
@@ -0,0 +1,515 @@
+use crate::models::{
+    Dependency, DependencyType, FileHashes, ModInfo, ModLoader, ProjectRef, ProviderKind,
+    ReleaseType,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const MANIFEST_FILE: &str = "cdl.toml";
+pub const LOCK_FILE: &str = "cdl.lock";
+
+pub type Result<T> = std::result::Result<T, InstanceError>;
+
+#[derive(Debug)]
+pub enum InstanceError {
+    IoError(io::Error),
+    TomlSerializeError(toml::ser::Error),
+    TomlDeserializeError(toml::de::Error),
+}
+
+impl Display for InstanceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "{}", e),
+            Self::TomlSerializeError(e) => write!(f, "{}", e),
+            Self::TomlDeserializeError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for InstanceError {}
+
+impl From<io::Error> for InstanceError {
+    fn from(e: io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<toml::ser::Error> for InstanceError {
+    fn from(e: toml::ser::Error) -> Self {
+        Self::TomlSerializeError(e)
+    }
+}
+
+impl From<toml::de::Error> for InstanceError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::TomlDeserializeError(e)
+    }
+}
+
+/// What the instance targets and which projects were explicitly asked for.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub game_version: String,
+    pub mod_loader: ModLoader,
+    /// Where mods come from unless asked otherwise, and where dependencies
+    /// are taken from when they are on it.
+    #[serde(default)]
+    pub provider: ProviderKind,
+    #[serde(default)]
+    pub mods: Vec<ProjectRef>,
+}
+
+/// Every file that has been installed into the instance, including
+/// dependencies that were pulled in transitively.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lock {
+    #[serde(default, rename = "mod")]
+    pub mods: Vec<LockedMod>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "RawLockedMod", try_from = "RawLockedMod")]
+pub struct LockedMod {
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub project_id: u64,
+    pub file_id: u64,
+    pub name: String,
+    pub file_name: String,
+    #[serde(default)]
+    pub download_url: String,
+    /// Hex-encoded SHA-256 of the file's contents.
+    #[serde(default)]
+    pub sha256: String,
+    /// When the file was uploaded, or empty if it was installed before cdl
+    /// kept track of it.
+    #[serde(default)]
+    pub file_date: String,
+    pub game_version: String,
+    pub mod_loader: ModLoader,
+    pub explicit: bool,
+    /// Whether the file was asked for by its ID, which updates and later
+    /// installs then keep instead of taking the latest file.
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub dependencies: Vec<ProjectRef>,
+    #[serde(default)]
+    pub incompatible: Vec<ProjectRef>,
+}
+
+impl LockedMod {
+    pub fn project(&self) -> ProjectRef {
+        ProjectRef::new(self.provider, self.project_id)
+    }
+}
+
+/// A locked mod as it is written, with its IDs the way the provider's
+/// website shows them.
+#[derive(Serialize, Deserialize)]
+struct RawLockedMod {
+    #[serde(default)]
+    provider: ProviderKind,
+    project_id: RawId,
+    file_id: RawId,
+    name: String,
+    file_name: String,
+    #[serde(default)]
+    download_url: String,
+    #[serde(default)]
+    sha256: String,
+    #[serde(default)]
+    file_date: String,
+    game_version: String,
+    mod_loader: ModLoader,
+    explicit: bool,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    dependencies: Vec<ProjectRef>,
+    #[serde(default)]
+    incompatible: Vec<ProjectRef>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawId {
+    /// Written by versions that stored every ID as a plain number.
+    Number(u64),
+    Formatted(String),
+}
+
+impl RawId {
+    fn parse(self, provider: ProviderKind) -> std::result::Result<u64, String> {
+        match self {
+            Self::Number(id) => Ok(id),
+            Self::Formatted(id) => provider
+                .provider()
+                .parse_id(&id)
+                .ok_or_else(|| format!("'{}' not a valid {} ID", id, provider)),
+        }
+    }
+}
+
+impl From<LockedMod> for RawLockedMod {
+    fn from(locked: LockedMod) -> Self {
+        let provider = locked.provider.provider();
+        Self {
+            provider: locked.provider,
+            project_id: RawId::Formatted(provider.format_id(locked.project_id)),
+            file_id: RawId::Formatted(provider.format_id(locked.file_id)),
+            name: locked.name,
+            file_name: locked.file_name,
+            download_url: locked.download_url,
+            sha256: locked.sha256,
+            file_date: locked.file_date,
+            game_version: locked.game_version,
+            mod_loader: locked.mod_loader,
+            explicit: locked.explicit,
+            pinned: locked.pinned,
+            dependencies: locked.dependencies,
+            incompatible: locked.incompatible,
+        }
+    }
+}
+
+impl TryFrom<RawLockedMod> for LockedMod {
+    type Error = String;
+
+    fn try_from(raw: RawLockedMod) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            provider: raw.provider,
+            project_id: raw.project_id.parse(raw.provider)?,
+            file_id: raw.file_id.parse(raw.provider)?,
+            name: raw.name,
+            file_name: raw.file_name,
+            download_url: raw.download_url,
+            sha256: raw.sha256,
+            file_date: raw.file_date,
+            game_version: raw.game_version,
+            mod_loader: raw.mod_loader,
+            explicit: raw.explicit,
+            pinned: raw.pinned,
+            dependencies: raw.dependencies,
+            incompatible: raw.incompatible,
+        })
+    }
+}
+
+impl From<&LockedMod> for ModInfo {
+    fn from(locked: &LockedMod) -> Self {
+        Self {
+            project_id: locked.project_id,
+            provider: locked.provider,
+            id: locked.file_id,
+            display_name: locked.name.clone(),
+            file_name: locked.file_name.clone(),
+            file_length: 0,
+            package_fingerprint: 0,
+            hashes: FileHashes::default(),
+            release_type: ReleaseType::default(),
+            file_date: locked.file_date.clone(),
+            download_url: locked.download_url.clone(),
+            dependencies: locked
+                .dependencies
+                .iter()
+                .map(|project| Dependency {
+                    provider: project.provider,
+                    addon_id: project.id,
+                    dep_type: DependencyType::Required,
+                })
+                .chain(locked.incompatible.iter().map(|project| Dependency {
+                    provider: project.provider,
+                    addon_id: project.id,
+                    dep_type: DependencyType::Incompatible,
+                }))
+                .collect(),
+        }
+    }
+}
+
+/// The state of an installed file compared to what the lock expects.
+#[derive(Debug, PartialEq)]
+pub enum FileStatus {
+    Ok,
+    Missing,
+    /// The file's contents differ from when it was installed.
+    Modified {
+        actual: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub dir: PathBuf,
+    pub manifest: Manifest,
+    pub lock: Lock,
+}
+
+impl Instance {
+    pub fn new(
+        dir: &Path,
+        game_version: &str,
+        mod_loader: &ModLoader,
+        provider: ProviderKind,
+    ) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            manifest: Manifest {
+                game_version: game_version.into(),
+                mod_loader: mod_loader.clone(),
+                provider,
+                mods: vec![],
+            },
+            lock: Lock::default(),
+        }
+    }
+
+    /// Loads the instance in `dir`, or `None` if it has no manifest.
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let manifest_path = dir.join(MANIFEST_FILE);
+        if !manifest_path.exists() {
+            return Ok(None);
+        }
+
+        let manifest = toml::from_str(&fs::read_to_string(manifest_path)?)?;
+
+        let lock_path = dir.join(LOCK_FILE);
+        let lock = if lock_path.exists() {
+            toml::from_str(&fs::read_to_string(lock_path)?)?
+        } else {
+            Lock::default()
+        };
+
+        Ok(Some(Self {
+            dir: dir.to_path_buf(),
+            manifest,
+            lock,
+        }))
+    }
+
+    /// Writes the manifest and lock, creating the instance folder if needed.
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.dir.join(MANIFEST_FILE),
+            toml::to_string(&self.manifest)?,
+        )?;
+        fs::write(self.dir.join(LOCK_FILE), toml::to_string(&self.lock)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, project: ProjectRef) -> Option<&LockedMod> {
+        self.lock.mods.iter().find(|m| m.project() == project)
+    }
+
+    /// Finds an installed mod by its project ID, name or file name.
+    pub fn find(&self, query: &str) -> Option<&LockedMod> {
+        let query = query.to_lowercase();
+        self.lock.mods.iter().find(|m| {
+            let id = m.provider.provider().format_id(m.project_id);
+            id.to_lowercase() == query
+                || m.project().to_string().to_lowercase() == query
+                || m.name.to_lowercase() == query
+                || m.file_name.to_lowercase() == query
+        })
+    }
+
+    /// Deletes the file of the given project and forgets about it.
+    pub fn remove(&mut self, project: ProjectRef) -> Result<Option<LockedMod>> {
+        let index = match self.lock.mods.iter().position(|m| m.project() == project) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let removed = self.lock.mods.remove(index);
+        self.manifest.mods.retain(|&p| p != project);
+
+        let path = self.dir.join(&removed.file_name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(Some(removed))
+    }
+
+    /// Deletes the installed file of the project of `info` if `info` is
+    /// another file under a different name, so that the instance never
+    /// holds two versions of one mod. Only call this once the new file is
+    /// in place, before recording it.
+    pub fn remove_replaced(&self, info: &ModInfo) -> Result<()> {
+        if let Some(old) = self.get(info.project()) {
+            let path = self.dir.join(&old.file_name);
+            if old.file_name != info.file_name && path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Installed mods that are neither explicitly requested nor required,
+    /// directly or transitively, by a mod that is.
+    pub fn orphans(&self) -> Vec<&LockedMod> {
+        let mut required = self
+            .lock
+            .mods
+            .iter()
+            .filter(|m| m.explicit)
+            .map(|m| m.project())
+            .collect::<Vec<_>>();
+
+        let mut i = 0;
+        while i < required.len() {
+            if let Some(m) = self.get(required[i]) {
+                for dep in &m.dependencies {
+                    if !required.contains(dep) {
+                        required.push(*dep);
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        self.lock
+            .mods
+            .iter()
+            .filter(|m| !required.contains(&m.project()))
+            .collect()
+    }
+
+    /// Rehashes the file of every installed mod, in the order of the lock.
+    pub fn verify(&self) -> Result<Vec<(&LockedMod, FileStatus)>> {
+        let mut statuses = vec![];
+        for locked in &self.lock.mods {
+            let path = self.dir.join(&locked.file_name);
+            let status = if !path.exists() {
+                FileStatus::Missing
+            } else {
+                let actual = crate::file_hash(path)?;
+                if locked.sha256.is_empty() || actual == locked.sha256 {
+                    FileStatus::Ok
+                } else {
+                    FileStatus::Modified { actual }
+                }
+            };
+            statuses.push((locked, status));
+        }
+        Ok(statuses)
+    }
+
+    /// Records `info` as installed, replacing any previous file of the same
+    /// project. A mod that has once been explicitly requested stays explicit
+    /// even if it is later pulled in as a dependency. The file must already
+    /// be present in the instance, as its hash is stored in the lock.
+    pub fn record(&mut self, info: &ModInfo, explicit: bool, pinned: bool) -> Result<()> {
+        let sha256 = crate::file_hash(self.dir.join(&info.file_name))?;
+
+        let project = info.project();
+        let explicit = explicit || self.manifest.mods.contains(&project);
+        if explicit && !self.manifest.mods.contains(&project) {
+            self.manifest.mods.push(project);
+        }
+
+        let locked = LockedMod {
+            provider: info.provider,
+            project_id: info.project_id,
+            file_id: info.id,
+            name: info.display_name.clone(),
+            file_name: info.file_name.clone(),
+            download_url: info.download_url.clone(),
+            sha256,
+            file_date: info.file_date.clone(),
+            game_version: self.manifest.game_version.clone(),
+            mod_loader: self.manifest.mod_loader.clone(),
+            explicit,
+            pinned,
+            dependencies: info
+                .hard_dependencies()
+                .iter()
+                .map(|d| d.project())
+                .collect(),
+            incompatible: info
+                .dependencies_of_type(DependencyType::Incompatible)
+                .iter()
+                .map(|d| d.project())
+                .collect(),
+        };
+
+        match self.lock.mods.iter_mut().find(|m| m.project() == project) {
+            Some(m) => *m = locked,
+            None => self.lock.mods.push(locked),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::locked;
+
+    #[test]
+    fn locked_ids() {
+        let mut modrinth = locked(1, true, &[]);
+        modrinth.provider = ProviderKind::Modrinth;
+        modrinth.project_id = ProviderKind::Modrinth
+            .provider()
+            .parse_id("P7dR8mSH")
+            .unwrap();
+        let lock = Lock {
+            mods: vec![locked(2, false, &[]), modrinth],
+        };
+
+        let written = toml::to_string(&lock).unwrap();
+        assert!(written.contains("project_id = \"P7dR8mSH\""));
+        assert!(written.contains("project_id = \"2\""));
+
+        let read = toml::from_str::<Lock>(&written).unwrap();
+        assert_eq!(
+            read.mods.iter().map(|m| m.project()).collect::<Vec<_>>(),
+            lock.mods.iter().map(|m| m.project()).collect::<Vec<_>>()
+        );
+
+        let legacy = written.replace("project_id = \"2\"", "project_id = 2");
+        let read = toml::from_str::<Lock>(&legacy).unwrap();
+        assert_eq!(read.mods[0].project_id, 2);
+    }
+
+    #[test]
+    fn orphans() {
+        let mut instance = Instance::new(
+            Path::new("."),
+            "1.16.4",
+            &ModLoader::Forge,
+            ProviderKind::CurseForge,
+        );
+        instance.lock.mods = vec![
+            locked(1, true, &[2]),
+            locked(2, false, &[3]),
+            locked(3, false, &[]),
+            locked(4, false, &[3]),
+            locked(5, true, &[]),
+        ];
+
+        let orphans = instance.orphans();
+        assert_eq!(
+            orphans.iter().map(|m| m.project_id).collect::<Vec<_>>(),
+            vec![4]
+        );
+
+        instance.lock.mods.remove(0);
+        let orphans = instance.orphans();
+        assert_eq!(
+            orphans.iter().map(|m| m.project_id).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+    }
+}
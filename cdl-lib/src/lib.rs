@@ -1,45 +1,64 @@
+pub mod cache;
+#[cfg(test)]
+mod fixtures;
 pub mod git;
+pub mod instance;
+pub mod mapping;
 pub mod models;
+pub mod provider;
+pub mod resolve;
 pub mod url;
 
-use models::{ModInfo, ModLoader, SearchResult, SortType};
+use async_std::{future, task};
+use cache::Cache;
+use futures::{pin_mut, stream, Stream, StreamExt, TryStreamExt};
+use futures::{AsyncReadExt, Future};
+use instance::{Instance, InstanceError, LockedMod};
+use mapping::Mapper;
+use models::{ModInfo, ProjectRef, ProviderKind, ReleaseType};
+use resolve::Plan;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
 use std::error::Error;
 use std::fmt;
-use std::fs::File;
-use std::io;
-use std::path::Path;
-
-pub async fn get_search_results(
-    query: &str,
-    version: &str,
-    amount: u8,
-    sort_type: &SortType,
-    mod_loader: &ModLoader,
-) -> surf::Result<Vec<SearchResult>> {
-    let url = url::search_url(query, version, amount, sort_type);
-    let mut results = surf::get(&url)
-        .await?
-        .body_json::<Vec<SearchResult>>()
-        .await?;
-
-    let mut i = 0;
-    while i != results.len() {
-        if mod_loader == &ModLoader::Forge && results[i].is_fabric() {
-            results.remove(i);
-        } else if mod_loader == &ModLoader::Fabric && !results[i].is_fabric() {
-            results.remove(i);
-        } else {
-            i += 1;
-        }
-    }
-
-    Ok(results)
-}
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use surf::{StatusCode, Url};
 
 #[derive(Debug)]
 pub enum DownloadError {
     IoError(io::Error),
     SurfError(surf::Error),
+    InstanceError(InstanceError),
+    HashMismatch {
+        file_name: String,
+        expected: String,
+        actual: String,
+    },
+    LengthMismatch {
+        file_name: String,
+        expected: u64,
+        actual: u64,
+    },
+    FingerprintMismatch {
+        file_name: String,
+        expected: u32,
+        actual: u32,
+    },
+    /// The server answered with something other than the file, where
+    /// `status` is that of the last response, if there was one.
+    BadResponse {
+        url: String,
+        status: Option<u16>,
+        reason: String,
+    },
+    /// Some files failed to download, each of which has already been
+    /// reported through a `MainError` or `DepError` event.
+    Incomplete {
+        failed: Vec<String>,
+    },
 }
 
 impl fmt::Display for DownloadError {
@@ -47,6 +66,94 @@ impl fmt::Display for DownloadError {
         match self {
             Self::IoError(e) => write!(f, "{}", e),
             Self::SurfError(e) => write!(f, "{}", e),
+            Self::InstanceError(e) => write!(f, "{}", e),
+            Self::HashMismatch {
+                file_name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "hash mismatch for {}: expected {}, got {}",
+                file_name, expected, actual
+            ),
+            Self::LengthMismatch {
+                file_name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} is {} bytes long, expected {}",
+                file_name, actual, expected
+            ),
+            Self::FingerprintMismatch {
+                file_name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "fingerprint mismatch for {}: expected {}, got {}",
+                file_name, expected, actual
+            ),
+            Self::BadResponse {
+                url,
+                status: Some(status),
+                reason,
+            } => write!(f, "bad response ({}) for {}: {}", status, url, reason),
+            Self::BadResponse {
+                url,
+                status: None,
+                reason,
+            } => write!(f, "bad response for {}: {}", url, reason),
+            Self::Incomplete { failed } => write!(
+                f,
+                "{} files failed to download: {}",
+                failed.len(),
+                failed.join(", ")
+            ),
+        }
+    }
+}
+
+impl DownloadError {
+    /// The name of the variant, for reporting errors in a machine-readable way.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::IoError(_) => "IoError",
+            Self::SurfError(_) => "SurfError",
+            Self::InstanceError(_) => "InstanceError",
+            Self::HashMismatch { .. } => "HashMismatch",
+            Self::LengthMismatch { .. } => "LengthMismatch",
+            Self::FingerprintMismatch { .. } => "FingerprintMismatch",
+            Self::BadResponse { .. } => "BadResponse",
+            Self::Incomplete { .. } => "Incomplete",
+        }
+    }
+}
+
+impl DownloadError {
+    /// Whether trying again might succeed, such as after a dropped
+    /// connection or a server error.
+    pub fn is_transient(&self) -> bool {
+        use io::ErrorKind::*;
+        match self {
+            Self::IoError(e) => !matches!(
+                e.kind(),
+                NotFound | PermissionDenied | AlreadyExists | InvalidInput | InvalidData
+            ),
+            Self::SurfError(e) => {
+                e.status().is_server_error()
+                    || e.status() == StatusCode::RequestTimeout
+                    || e.status() == StatusCode::TooManyRequests
+            }
+            Self::BadResponse {
+                status: Some(status),
+                ..
+            } => *status >= 500 || *status == 408 || *status == 429,
+            Self::LengthMismatch { .. } | Self::FingerprintMismatch { .. } => true,
+            Self::InstanceError(_)
+            | Self::HashMismatch { .. }
+            | Self::BadResponse { .. }
+            | Self::Incomplete { .. } => false,
         }
     }
 }
@@ -65,110 +172,845 @@ impl From<surf::Error> for DownloadError {
     }
 }
 
-pub async fn download(url: &str, file_name: &str) -> Result<(), DownloadError> {
-    // The url from the API references an endpoint that redirects
-    // to another url for downloading. reqwest handled
-    // this fine, but surf seems to not follow the redirect.
-    // Thus, one request is made in order to find the "real" url,
-    // before sending a GET to it.
-    let tmp = surf::get(&url).await?;
-    let loc = tmp.header("location");
-    let url = loc.map(|h| h.as_str()).unwrap_or(&url);
+impl From<InstanceError> for DownloadError {
+    fn from(e: InstanceError) -> Self {
+        Self::InstanceError(e)
+    }
+}
+
+/// How failed downloads are retried.
+#[derive(Clone, Debug)]
+pub struct Retry {
+    /// How many times a download is tried again after a transient error.
+    pub retries: u32,
+    /// How long to wait for the server to respond or to send more data.
+    pub timeout: Duration,
+}
+
+impl Retry {
+    /// How long to wait before the given retry, doubling every time.
+    fn backoff(&self, retry: u32) -> Duration {
+        Duration::from_secs(1 << retry.saturating_sub(1).min(5))
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Downloads `url` to `file_name`, giving up if the server takes longer
+/// than `timeout` to respond or to send the next part of the file. If
+/// `file_name` already exists, only the rest of the file is requested and
+/// appended to it. `on_progress` is called with the number of bytes in the
+/// file so far and its total size, if the server says.
+pub async fn download<P: AsRef<Path>, F: Fn(u64, Option<u64>)>(
+    url: &str,
+    file_name: P,
+    timeout: Duration,
+    on_progress: F,
+) -> Result<(), DownloadError> {
+    let file_name = file_name.as_ref();
+
+    let offset = fs::metadata(file_name).map(|m| m.len()).unwrap_or(0);
+    let mut source = get_following_redirects(url, offset, timeout).await?;
+    if source.status() == StatusCode::RequestedRangeNotSatisfiable && offset > 0 {
+        // What was downloaded before is no prefix of this file, start over.
+        source = get_following_redirects(url, 0, timeout).await?;
+    }
+
+    if !source.status().is_success() {
+        return Err(bad_response(
+            url,
+            &source,
+            "the server didn't send the file",
+        ));
+    }
+    if source
+        .content_type()
+        .is_some_and(|mime| mime.essence() == "text/html")
+    {
+        return Err(bad_response(
+            url,
+            &source,
+            "got a web page instead of the file",
+        ));
+    }
+
+    if let Some(parent) = file_name.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Servers that don't support ranges send the whole file instead.
+    let resume = source.status() == StatusCode::PartialContent;
+    let mut received = if resume { offset } else { 0 };
+    let total = source.len().map(|len| received + len as u64);
+    on_progress(received, total);
+
+    let mut dest = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resume)
+        .truncate(!resume)
+        .open(file_name)?;
+
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = within(timeout, source.read(&mut buf)).await??;
+        if n == 0 {
+            break;
+        }
+        dest.write_all(&buf[..n])?;
+        received += n as u64;
+        on_progress(received, total);
+    }
 
-    let source = surf::get(&url).recv_bytes().await?;
-    let mut dest = File::create(file_name)?;
-    io::copy(&mut source.as_slice(), &mut dest)?;
     Ok(())
 }
 
+/// The most redirects followed for a single download.
+const MAX_REDIRECTS: usize = 10;
+
+/// Sends a GET for `url`, asking for everything from byte `offset` onwards
+/// unless it is 0, and follows any redirects. The download URLs from the
+/// API redirect to where the file is actually hosted, and surf doesn't
+/// follow redirects by itself.
+async fn get_following_redirects(
+    url: &str,
+    offset: u64,
+    timeout: Duration,
+) -> Result<surf::Response, DownloadError> {
+    let invalid = |reason: String| DownloadError::BadResponse {
+        url: url.into(),
+        status: None,
+        reason,
+    };
+
+    let mut current = Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+    let mut visited = vec![];
+
+    loop {
+        if visited.contains(&current) {
+            return Err(invalid(format!("redirect loop at {}", current)));
+        }
+        if visited.len() > MAX_REDIRECTS {
+            return Err(invalid(format!("more than {} redirects", MAX_REDIRECTS)));
+        }
+
+        let mut request = surf::get(&current);
+        if offset > 0 {
+            request = request.header("Range", format!("bytes={}-", offset));
+        }
+        let response = within(timeout, request).await??;
+
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+
+        let location = match response.header("location") {
+            Some(location) => location.as_str().to_owned(),
+            None => return Err(bad_response(url, &response, "redirect without a location")),
+        };
+        let next = current
+            .join(&location)
+            .map_err(|e| invalid(format!("bad redirect to '{}': {}", location, e)))?;
+        visited.push(current);
+        current = next;
+    }
+}
+
+fn bad_response(url: &str, response: &surf::Response, reason: &str) -> DownloadError {
+    DownloadError::BadResponse {
+        url: url.into(),
+        status: Some(response.status().into()),
+        reason: reason.into(),
+    }
+}
+
+/// Runs `f`, failing with `TimedOut` if it takes longer than `timeout`.
+async fn within<T, F: Future<Output = T>>(timeout: Duration, f: F) -> io::Result<T> {
+    future::timeout(timeout, f)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "the server stopped responding"))
+}
+
+/// Downloads `info` to `path`, checking its length and fingerprint against
+/// the ones the API reported. The file is written next to `path` first and
+/// only moved into place once it has been verified, so an interrupted or
+/// corrupted download never leaves a broken file behind. An interrupted
+/// download is resumed the next time, while a corrupted one starts over.
+pub async fn download_verified<F: Fn(u64, Option<u64>)>(
+    info: &ModInfo,
+    path: &Path,
+    timeout: Duration,
+    on_progress: F,
+) -> Result<(), DownloadError> {
+    let part = part_path(path);
+    download(&info.download_url, &part, timeout, on_progress).await?;
+
+    match verify(info, &part) {
+        Ok(_) => Ok(fs::rename(part, path)?),
+        Err(e) => {
+            fs::remove_file(part)?;
+            Err(e)
+        }
+    }
+}
+
+/// Where a file is downloaded to before it is verified.
+fn part_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
+
+/// Checks the file at `path` against the length, fingerprint and strongest
+/// hash of `info`, skipping whichever of them is unknown.
+pub fn verify(info: &ModInfo, path: &Path) -> Result<(), DownloadError> {
+    let length = fs::metadata(path)?.len();
+    if info.file_length != 0 && length != info.file_length {
+        return Err(DownloadError::LengthMismatch {
+            file_name: info.file_name.clone(),
+            expected: info.file_length,
+            actual: length,
+        });
+    }
+
+    if info.package_fingerprint != 0 {
+        let actual = file_fingerprint(path)?;
+        if actual != info.package_fingerprint {
+            return Err(DownloadError::FingerprintMismatch {
+                file_name: info.file_name.clone(),
+                expected: info.package_fingerprint,
+                actual,
+            });
+        }
+    }
+
+    let (expected, actual) = if !info.hashes.sha512.is_empty() {
+        (&info.hashes.sha512, digest_file::<Sha512>(path)?)
+    } else if !info.hashes.sha1.is_empty() {
+        (&info.hashes.sha1, digest_file::<Sha1>(path)?)
+    } else {
+        return Ok(());
+    };
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(DownloadError::HashMismatch {
+            file_name: info.file_name.clone(),
+            expected: expected.clone(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+/// The fingerprint CurseForge reports for files, which is the 32-bit
+/// MurmurHash2 of the contents with all whitespace removed, seeded with 1.
+pub fn fingerprint(bytes: &[u8]) -> u32 {
+    let mut hasher = Fingerprint::new(non_whitespace(bytes));
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+/// Like `fingerprint`, but reads the file in pieces instead of all at once.
+/// The file is read twice, as the hash starts from the length of the
+/// contents without whitespace.
+pub fn file_fingerprint<P: AsRef<Path>>(path: P) -> io::Result<u32> {
+    let path = path.as_ref();
+    let mut len = 0u32;
+    for_each_chunk(path, |chunk| len = len.wrapping_add(non_whitespace(chunk)))?;
+
+    let mut hasher = Fingerprint::new(len);
+    for_each_chunk(path, |chunk| hasher.update(chunk))?;
+    Ok(hasher.finish())
+}
+
+fn for_each_chunk<F: FnMut(&[u8])>(path: &Path, mut f: F) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        f(chunk);
+        let read = chunk.len();
+        reader.consume(read);
+    }
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, 9 | 10 | 13 | 32)
+}
+
+fn non_whitespace(bytes: &[u8]) -> u32 {
+    bytes.iter().filter(|&&b| !is_whitespace(b)).count() as u32
+}
+
+/// MurmurHash2 of bytes given in any number of pieces, skipping
+/// whitespace.
+struct Fingerprint {
+    h: u32,
+    tail: [u8; 4],
+    tail_len: usize,
+}
+
+impl Fingerprint {
+    const M: u32 = 0x5bd1_e995;
+
+    /// Takes the length of the whole input, without whitespace.
+    fn new(len: u32) -> Self {
+        Self {
+            h: 1 ^ len,
+            tail: [0; 4],
+            tail_len: 0,
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes.iter().filter(|&&b| !is_whitespace(b)) {
+            self.tail[self.tail_len] = b;
+            self.tail_len += 1;
+            if self.tail_len == 4 {
+                let mut k = u32::from_le_bytes(self.tail);
+                k = k.wrapping_mul(Self::M);
+                k ^= k >> 24;
+                k = k.wrapping_mul(Self::M);
+                self.h = self.h.wrapping_mul(Self::M) ^ k;
+                self.tail_len = 0;
+            }
+        }
+    }
+
+    fn finish(self) -> u32 {
+        let mut h = self.h;
+        if self.tail_len != 0 {
+            for (i, &b) in self.tail[..self.tail_len].iter().enumerate() {
+                h ^= (b as u32) << (8 * i);
+            }
+            h = h.wrapping_mul(Self::M);
+        }
+
+        h ^= h >> 13;
+        h = h.wrapping_mul(Self::M);
+        h ^ (h >> 15)
+    }
+}
+
+/// Returns the hex-encoded SHA-256 of the file at `path`.
+pub fn file_hash<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    digest_file::<Sha256>(path.as_ref())
+}
+
+fn digest_file<D: Digest + Write>(path: &Path) -> io::Result<String> {
+    let mut hasher = D::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
 pub enum EventType<'a> {
     MainAlreadyDownloaded(&'a ModInfo),
     MainDownloading(&'a ModInfo),
     MainDownloaded(&'a ModInfo),
+    MainCached(&'a ModInfo),
+    /// Bytes of the file downloaded so far, and its total size if known.
+    MainProgress(&'a ModInfo, u64, Option<u64>),
+    /// A download failed and is about to be tried again, for the given
+    /// attempt, counting the first one.
+    MainRetrying(&'a ModInfo, u32, &'a DownloadError),
     MainError(&'a ModInfo, &'a DownloadError),
     DepAlreadyDownloaded(&'a ModInfo),
     DepDownloading(&'a ModInfo),
     DepDownloaded(&'a ModInfo),
+    DepCached(&'a ModInfo),
+    DepProgress(&'a ModInfo, u64, Option<u64>),
+    DepRetrying(&'a ModInfo, u32, &'a DownloadError),
     DepError(&'a ModInfo, &'a DownloadError),
 }
 
-pub async fn download_all<F: Fn(EventType)>(
-    game_version: &str,
-    results: &[&SearchResult],
-    on_event: F,
-) -> surf::Result<()> {
-    use EventType::*;
-    let mut already_downloaded = Vec::<u32>::new();
-    for result in results {
-        let m = get_with_dependencies(game_version, result.id).await?;
-        if let Some((first, rest)) = m.split_first() {
-            if Path::new(&first.file_name).exists() {
-                continue;
-            }
+impl<'a> EventType<'a> {
+    fn already_downloaded(info: &'a ModInfo, explicit: bool) -> Self {
+        if explicit {
+            Self::MainAlreadyDownloaded(info)
+        } else {
+            Self::DepAlreadyDownloaded(info)
+        }
+    }
 
-            if already_downloaded.contains(&first.id) {
-                on_event(MainAlreadyDownloaded(&first));
-                continue;
-            }
+    fn downloading(info: &'a ModInfo, explicit: bool) -> Self {
+        if explicit {
+            Self::MainDownloading(info)
+        } else {
+            Self::DepDownloading(info)
+        }
+    }
+
+    fn downloaded(info: &'a ModInfo, explicit: bool) -> Self {
+        if explicit {
+            Self::MainDownloaded(info)
+        } else {
+            Self::DepDownloaded(info)
+        }
+    }
 
-            on_event(MainDownloading(&first));
-            match download(&first.download_url, &first.file_name).await {
-                Ok(_) => {
-                    already_downloaded.push(first.id);
-                    on_event(MainDownloaded(&first));
+    fn cached(info: &'a ModInfo, explicit: bool) -> Self {
+        if explicit {
+            Self::MainCached(info)
+        } else {
+            Self::DepCached(info)
+        }
+    }
+
+    fn progress(info: &'a ModInfo, explicit: bool, received: u64, total: Option<u64>) -> Self {
+        if explicit {
+            Self::MainProgress(info, received, total)
+        } else {
+            Self::DepProgress(info, received, total)
+        }
+    }
+
+    fn retrying(info: &'a ModInfo, explicit: bool, attempt: u32, e: &'a DownloadError) -> Self {
+        if explicit {
+            Self::MainRetrying(info, attempt, e)
+        } else {
+            Self::DepRetrying(info, attempt, e)
+        }
+    }
+
+    fn error(info: &'a ModInfo, explicit: bool, e: &'a DownloadError) -> Self {
+        if explicit {
+            Self::MainError(info, e)
+        } else {
+            Self::DepError(info, e)
+        }
+    }
+}
+
+/// Where a file placed into an instance came from.
+enum Fetched {
+    Downloaded,
+    Cached,
+}
+
+impl Fetched {
+    fn event(self, info: &ModInfo, explicit: bool) -> EventType<'_> {
+        match self {
+            Self::Downloaded => EventType::downloaded(info, explicit),
+            Self::Cached => EventType::cached(info, explicit),
+        }
+    }
+}
+
+/// Places `info` at `path`, taking it from the cache if it is there and
+/// downloading it into the cache otherwise.
+async fn fetch<F: Fn(u64, Option<u64>)>(
+    info: &ModInfo,
+    path: &Path,
+    cache: Option<&Cache>,
+    timeout: Duration,
+    on_progress: F,
+) -> Result<Fetched, DownloadError> {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            download_verified(info, path, timeout, on_progress).await?;
+            return Ok(Fetched::Downloaded);
+        }
+    };
+
+    if let Some(cached) = cache.get(info) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        cache::link(&cached, path)?;
+        return Ok(Fetched::Cached);
+    }
+
+    // The file may be a link into the cache, which is never written through
+    // as the download only replaces it once it has been verified.
+    download_verified(info, path, timeout, on_progress).await?;
+    // Failing to cache a file doesn't make the download any less successful.
+    let _ = cache.insert(info, path);
+    Ok(Fetched::Downloaded)
+}
+
+/// Fetches every mod into `dir`, with at most `jobs` downloads in flight,
+/// retrying transient errors as `retry` says. `Downloading` events are
+/// emitted as each download starts, while the results are yielded in the
+/// same order as `mods`.
+fn download_concurrently<'a, F: Fn(EventType)>(
+    dir: &'a Path,
+    mods: Vec<(&'a ModInfo, bool)>,
+    jobs: usize,
+    cache: Option<&'a Cache>,
+    retry: &'a Retry,
+    on_event: &'a F,
+) -> impl Stream<Item = ((&'a ModInfo, bool), Result<Fetched, DownloadError>)> + 'a {
+    stream::iter(mods)
+        .map(move |(info, explicit)| async move {
+            on_event(EventType::downloading(info, explicit));
+            let path = dir.join(&info.file_name);
+
+            let mut retries = 0;
+            let result = loop {
+                let on_progress = |received, total: Option<u64>| {
+                    let total = total.or(Some(info.file_length).filter(|&l| l != 0));
+                    on_event(EventType::progress(info, explicit, received, total));
+                };
+                match fetch(info, &path, cache, retry.timeout, on_progress).await {
+                    Err(e) if retries < retry.retries && e.is_transient() => {
+                        retries += 1;
+                        on_event(EventType::retrying(info, explicit, retries + 1, &e));
+                        task::sleep(retry.backoff(retries)).await;
+                    }
+                    result => break result,
                 }
-                Err(e) => on_event(MainError(&first, &e)),
+            };
+
+            ((info, explicit), result)
+        })
+        .buffered(jobs.max(1))
+}
+
+/// Downloads every mod in the plan that is not present in the instance yet,
+/// recording each installed file in the instance's manifest and lock file.
+/// Mods the plan marks as explicit are only recorded as such if `explicit`
+/// is set. Returns the names of the files that failed to download.
+async fn install_plan<F: Fn(EventType)>(
+    instance: &mut Instance,
+    plan: &Plan,
+    explicit: bool,
+    jobs: usize,
+    cache: Option<&Cache>,
+    retry: &Retry,
+    on_event: &F,
+) -> Result<Vec<String>, DownloadError> {
+    let pinned = |info: &ModInfo| {
+        plan.mods
+            .iter()
+            .any(|m| m.pinned && m.info.project() == info.project())
+    };
+
+    let mut mods = vec![];
+    for planned in &plan.mods {
+        let info = &planned.info;
+        let explicit = explicit && planned.explicit;
+
+        if instance.dir.join(&info.file_name).exists() {
+            instance.remove_replaced(info)?;
+            instance.record(info, explicit, planned.pinned)?;
+            on_event(EventType::already_downloaded(info, explicit));
+        } else {
+            mods.push((info, explicit));
+        }
+    }
+    instance.save()?;
+
+    let dir = instance.dir.clone();
+    let downloads = download_concurrently(&dir, mods, jobs, cache, retry, on_event);
+    pin_mut!(downloads);
+
+    let mut failed = vec![];
+    while let Some(((info, explicit), result)) = downloads.next().await {
+        match result {
+            Ok(fetched) => {
+                instance.remove_replaced(info)?;
+                instance.record(info, explicit, pinned(info))?;
+                instance.save()?;
+                on_event(fetched.event(info, explicit));
             }
+            Err(e) => {
+                on_event(EventType::error(info, explicit, &e));
+                failed.push(info.file_name.clone());
+            }
+        }
+    }
 
-            for r in rest {
-                if Path::new(&r.file_name).exists() {
-                    continue;
-                }
+    Ok(failed)
+}
 
-                if already_downloaded.contains(&r.id) {
-                    on_event(DepAlreadyDownloaded(&r));
-                    continue;
-                }
+fn incomplete(failed: Vec<String>) -> Result<(), DownloadError> {
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(DownloadError::Incomplete { failed })
+    }
+}
+
+/// Downloads every mod in a plan from `resolve::resolve` into `instance`,
+/// with at most `jobs` downloads in flight. Files found in `cache` are
+/// linked into the instance instead of being downloaded. Every file is
+/// tried even if others fail, after which the failed ones are returned as
+/// `DownloadError::Incomplete`.
+pub async fn download_all<F: Fn(EventType)>(
+    instance: &mut Instance,
+    plan: &Plan,
+    jobs: usize,
+    cache: Option<&Cache>,
+    retry: &Retry,
+    on_event: F,
+) -> Result<(), DownloadError> {
+    incomplete(install_plan(instance, plan, true, jobs, cache, retry, &on_event).await?)
+}
+
+/// An installed mod for which a newer file is available.
+#[derive(Debug)]
+pub struct Update {
+    pub old: LockedMod,
+    pub new: ModInfo,
+}
+
+impl Update {
+    /// Hard dependencies of the new file that are not installed yet.
+    pub fn new_dependencies(&self, instance: &Instance) -> Vec<ProjectRef> {
+        self.new
+            .hard_dependencies()
+            .iter()
+            .map(|d| d.project())
+            .filter(|&project| instance.get(project).is_none())
+            .collect()
+    }
+}
+
+/// Whether `new` replaces the installed file rather than going back to an
+/// older one, which the latest file can be when the installed one has been
+/// withdrawn or is less stable than `min_release`. Upload dates are RFC 3339
+/// timestamps in UTC and so compare as strings. Files installed before
+/// their dates were kept are compared by ID on CurseForge, where IDs only
+/// grow, and are otherwise assumed to be older.
+fn is_newer(new: &ModInfo, locked: &LockedMod) -> bool {
+    if new.id == locked.file_id {
+        false
+    } else if !new.file_date.is_empty() && !locked.file_date.is_empty() {
+        new.file_date > locked.file_date
+    } else {
+        locked.provider != ProviderKind::CurseForge || new.id > locked.file_id
+    }
+}
+
+/// Looks up the latest file of every installed mod that isn't pinned, for
+/// the instance's game version and mod loader and at least as stable as
+/// `min_release`, returning the ones that are newer than what is installed.
+/// Dependencies of the new files are mapped to the instance's provider like
+/// `resolve::Resolver` does.
+pub async fn find_updates(
+    instance: &Instance,
+    min_release: ReleaseType,
+    jobs: usize,
+) -> surf::Result<Vec<Update>> {
+    let game_version = &instance.manifest.game_version;
+    let mod_loader = &instance.manifest.mod_loader;
+    let preferred = instance.manifest.provider;
+    let mapper = &Mapper::new(game_version, mod_loader);
+
+    let updates = stream::iter(instance.lock.mods.iter().filter(|m| !m.pinned))
+        .map(|locked| async move {
+            let file = locked
+                .provider
+                .provider()
+                .get_latest_file(game_version, mod_loader, min_release, locked.project_id)
+                .await?;
+            let mut new = match file.filter(|f| is_newer(f, locked)) {
+                Some(new) => new,
+                None => return Ok(None),
+            };
+            mapper.map_dependencies(&mut new, preferred).await?;
+            Ok::<_, surf::Error>(Some(Update {
+                old: locked.clone(),
+                new,
+            }))
+        })
+        .buffered(jobs.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(updates.into_iter().flatten().collect())
+}
+
+/// Replaces the files of every update, then downloads the hard dependencies
+/// the new files require that are not yet installed. Fails with
+/// `DownloadError::Incomplete` if any file failed, like `download_all`.
+pub async fn update_all<F: Fn(EventType)>(
+    instance: &mut Instance,
+    updates: &[Update],
+    min_release: ReleaseType,
+    jobs: usize,
+    cache: Option<&Cache>,
+    retry: &Retry,
+    on_event: F,
+) -> Result<(), DownloadError> {
+    let game_version = instance.manifest.game_version.clone();
+    let mod_loader = instance.manifest.mod_loader.clone();
+    let mut new_dependencies = Vec::<ProjectRef>::new();
+
+    let dir = instance.dir.clone();
+    let mods = updates.iter().map(|u| (&u.new, u.old.explicit)).collect();
+    let downloads =
+        download_concurrently(&dir, mods, jobs, cache, retry, &on_event).zip(stream::iter(updates));
+    pin_mut!(downloads);
 
-                on_event(DepDownloading(&r));
-                match download(&r.download_url, &r.file_name).await {
-                    Ok(_) => {
-                        already_downloaded.push(r.id);
-                        on_event(DepDownloaded(&r));
+    let mut failed = vec![];
+    while let Some((((new, explicit), result), update)) = downloads.next().await {
+        match result {
+            Ok(fetched) => {
+                instance.remove_replaced(new)?;
+
+                for project in update.new_dependencies(instance) {
+                    if !new_dependencies.contains(&project) {
+                        new_dependencies.push(project);
                     }
-                    Err(e) => on_event(DepError(&r, &e)),
                 }
+
+                instance.record(new, explicit, false)?;
+                instance.save()?;
+                on_event(fetched.event(new, explicit));
+            }
+            Err(e) => {
+                on_event(EventType::error(new, explicit, &e));
+                failed.push(new.file_name.clone());
             }
         }
     }
+
+    let plan = resolve::resolve(
+        instance.manifest.provider,
+        &game_version,
+        &mod_loader,
+        min_release,
+        &new_dependencies,
+        jobs,
+    )
+    .await?;
+    failed.extend(install_plan(instance, &plan, false, jobs, cache, retry, &on_event).await?);
+    incomplete(failed)
+}
+
+/// Downloads exactly the files recorded in the instance's lock file without
+/// resolving anything, failing on the first file whose hash does not match.
+pub async fn install_locked<F: Fn(EventType)>(
+    instance: &Instance,
+    jobs: usize,
+    cache: Option<&Cache>,
+    retry: &Retry,
+    on_event: F,
+) -> Result<(), DownloadError> {
+    let mut infos = vec![];
+    for locked in &instance.lock.mods {
+        let info = ModInfo::from(locked);
+        let path = instance.dir.join(&locked.file_name);
+
+        if path.exists() && (locked.sha256.is_empty() || file_hash(&path)? == locked.sha256) {
+            on_event(EventType::already_downloaded(&info, locked.explicit));
+        } else {
+            infos.push((info, locked));
+        }
+    }
+
+    let mods = infos
+        .iter()
+        .map(|(info, locked)| (info, locked.explicit))
+        .collect();
+    let downloads = download_concurrently(&instance.dir, mods, jobs, cache, retry, &on_event)
+        .zip(stream::iter(infos.iter().map(|(_, locked)| locked)));
+    pin_mut!(downloads);
+
+    while let Some((((info, explicit), result), locked)) = downloads.next().await {
+        let fetched = match result {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                on_event(EventType::error(info, explicit, &e));
+                return Err(e);
+            }
+        };
+
+        let path = instance.dir.join(&locked.file_name);
+        let actual = file_hash(&path)?;
+        if !locked.sha256.is_empty() && actual != locked.sha256 {
+            fs::remove_file(&path)?;
+            let e = DownloadError::HashMismatch {
+                file_name: locked.file_name.clone(),
+                expected: locked.sha256.clone(),
+                actual,
+            };
+            on_event(EventType::error(info, explicit, &e));
+            return Err(e);
+        }
+
+        on_event(fetched.event(info, explicit));
+    }
+
     Ok(())
 }
 
-#[async_recursion::async_recursion]
-async fn get_with_dependencies(game_version: &str, mod_id: u32) -> surf::Result<Vec<ModInfo>> {
-    let url = url::mod_url(mod_id);
-    let result = surf::get(&url).recv_json::<SearchResult>().await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{info, locked};
 
-    let file_id = result
-        .get_file_by_version(game_version)
-        .map(|file| file.project_file_id);
+    #[test]
+    fn newer_files() {
+        let installed = locked(1, true, &[]);
+        assert!(is_newer(&info(1, 20), &installed));
+        assert!(!is_newer(&info(1, 10), &installed));
+        // A withdrawn file can leave an older one as the latest.
+        assert!(!is_newer(&info(1, 5), &installed));
 
-    if let None = file_id {
-        return Ok(vec![]);
+        let installed = LockedMod {
+            file_date: "2021-02-01T12:00:00Z".into(),
+            ..installed
+        };
+        let dated = |id, file_date: &str| ModInfo {
+            file_date: file_date.into(),
+            ..info(1, id)
+        };
+        assert!(is_newer(&dated(5, "2021-03-01T12:00:00Z"), &installed));
+        assert!(!is_newer(&dated(20, "2021-01-01T12:00:00Z"), &installed));
     }
 
-    let file = surf::get(&url::info_url(result.id, file_id.unwrap()))
-        .recv_json::<ModInfo>()
-        .await?;
+    #[test]
+    fn fingerprints() {
+        assert_eq!(fingerprint(b""), 1540447798);
+        assert_eq!(fingerprint(b"The quick brown fox"), 2287222251);
+        assert_eq!(fingerprint(b"hello world"), 2824650221);
+        assert_eq!(
+            fingerprint(b"hello\r\n\tworld "),
+            fingerprint(b"helloworld")
+        );
+    }
+
+    #[test]
+    fn hashes() {
+        let path = std::env::temp_dir().join(format!("cdl-hashes-{}", std::process::id()));
+        fs::write(&path, "hello world").unwrap();
 
-    let mut mods: Vec<ModInfo> = vec![];
+        let mut info = crate::fixtures::info(1, 10);
+        info.hashes.sha1 = "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed".into();
+        let sha1 = verify(&info, &path);
+        info.hashes.sha512 = "0".repeat(128);
+        let sha512 = verify(&info, &path);
 
-    for dep in file.hard_dependencies() {
-        let foo = get_with_dependencies(game_version, dep.addon_id).await?;
-        mods.extend(foo);
+        fs::remove_file(&path).unwrap();
+        assert!(sha1.is_ok());
+        assert!(matches!(sha512, Err(DownloadError::HashMismatch { .. })));
     }
 
-    mods.insert(0, file);
+    #[test]
+    fn file_fingerprints() {
+        let path = std::env::temp_dir().join(format!("cdl-fingerprint-{}", std::process::id()));
+        // Longer than the reader's buffer, so that it is hashed in pieces
+        // that don't line up with the hash's blocks.
+        let contents = b"The quick\r\nbrown fox. ".repeat(1000);
+        fs::write(&path, &contents).unwrap();
 
-    Ok(mods)
+        let actual = file_fingerprint(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(actual.unwrap(), fingerprint(&contents));
+    }
 }
@@ -0,0 +1,134 @@
+use crate::models::{ModInfo, ModLoader, ProjectRef, ProviderKind, ReleaseType};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Libraries that are on both providers under different slugs, or that are
+/// depended on so often that they are worth not looking up, as their
+/// CurseForge ID and Modrinth ID.
+const KNOWN: &[(u64, &str)] = &[
+    // Fabric API
+    (306612, "P7dR8mSH"),
+    // Cloth Config API
+    (348521, "9s6osm5g"),
+    // Architectury API
+    (419699, "lhGA9TYQ"),
+];
+
+/// Finds the same project on another provider, so that a mod from one
+/// provider that depends on a library can share it with mods from another
+/// instead of each bringing their own copy. Projects are matched through
+/// `KNOWN` first and by their slug otherwise, and only if the match has a
+/// file for the game version and mod loader.
+pub struct Mapper<'a> {
+    game_version: &'a str,
+    mod_loader: &'a ModLoader,
+    mapped: RefCell<HashMap<(ProjectRef, ProviderKind), ProjectRef>>,
+}
+
+impl<'a> Mapper<'a> {
+    pub fn new(game_version: &'a str, mod_loader: &'a ModLoader) -> Self {
+        Self {
+            game_version,
+            mod_loader,
+            mapped: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the project on `to` that is the same as `project`, or
+    /// `project` itself if there is none or it has no compatible file.
+    pub async fn map(&self, project: ProjectRef, to: ProviderKind) -> surf::Result<ProjectRef> {
+        if project.provider == to {
+            return Ok(project);
+        }
+        if let Some(&mapped) = self.mapped.borrow().get(&(project, to)) {
+            return Ok(mapped);
+        }
+
+        let found = match known(project, to) {
+            Some(found) => Some(found),
+            None => {
+                let slug = project.provider.provider().get_mod(project.id).await?.slug;
+                match slug.as_str() {
+                    "" => None,
+                    slug => to
+                        .provider()
+                        .find_mod(slug, self.game_version)
+                        .await?
+                        .map(|r| ProjectRef::new(to, r.id)),
+                }
+            }
+        };
+
+        let mapped = match found {
+            Some(found) if self.has_file(found).await? => found,
+            _ => project,
+        };
+
+        self.mapped.borrow_mut().insert((project, to), mapped);
+        Ok(mapped)
+    }
+
+    /// Whether the project has a file of any release type for the game
+    /// version and mod loader.
+    async fn has_file(&self, project: ProjectRef) -> surf::Result<bool> {
+        let file = project
+            .provider
+            .provider()
+            .get_latest_file(
+                self.game_version,
+                self.mod_loader,
+                ReleaseType::Alpha,
+                project.id,
+            )
+            .await?;
+        Ok(file.is_some())
+    }
+
+    /// Points every dependency of `info` at the same project on `to` where
+    /// there is one.
+    pub async fn map_dependencies(&self, info: &mut ModInfo, to: ProviderKind) -> surf::Result<()> {
+        for dep in &mut info.dependencies {
+            let mapped = self.map(dep.project(), to).await?;
+            dep.provider = mapped.provider;
+            dep.addon_id = mapped.id;
+        }
+        Ok(())
+    }
+}
+
+fn known(project: ProjectRef, to: ProviderKind) -> Option<ProjectRef> {
+    let modrinth = ProviderKind::Modrinth.provider();
+    KNOWN.iter().find_map(|&(curseforge, other)| {
+        let other = modrinth.parse_id(other)?;
+        match (project.provider, to) {
+            (ProviderKind::CurseForge, ProviderKind::Modrinth) if project.id == curseforge => {
+                Some(ProjectRef::new(to, other))
+            }
+            (ProviderKind::Modrinth, ProviderKind::CurseForge) if project.id == other => {
+                Some(ProjectRef::new(to, curseforge))
+            }
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_projects() {
+        let curseforge = ProjectRef::new(ProviderKind::CurseForge, 306612);
+        let modrinth = "modrinth:P7dR8mSH".parse::<ProjectRef>().unwrap();
+
+        assert_eq!(known(curseforge, ProviderKind::Modrinth), Some(modrinth));
+        assert_eq!(known(modrinth, ProviderKind::CurseForge), Some(curseforge));
+        assert_eq!(
+            known(
+                ProjectRef::new(ProviderKind::CurseForge, 1),
+                ProviderKind::Modrinth
+            ),
+            None
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
@@ -23,14 +23,296 @@ impl FromStr for ModLoader {
     }
 }
 
-impl ToString for ModLoader {
-    fn to_string(&self) -> String {
-        match self {
-            Self::Forge => "Forge".into(),
-            Self::Fabric => "Fabric".into(),
-            Self::Both => "Forge/Fabric".into(),
+impl Display for ModLoader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match &self {
+                Self::Forge => "Forge",
+                Self::Fabric => "Fabric",
+                Self::Both => "Forge/Fabric",
+            }
+        )
+    }
+}
+
+/// Where mods are searched for and downloaded from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ProviderKind {
+    #[default]
+    CurseForge,
+    Modrinth,
+}
+
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "curseforge" => Ok(Self::CurseForge),
+            "modrinth" => Ok(Self::Modrinth),
+
+            s => Err(format!("'{}' not a valid provider", s)),
+        }
+    }
+}
+
+impl Display for ProviderKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match &self {
+                Self::CurseForge => "CurseForge",
+                Self::Modrinth => "Modrinth",
+            }
+        )
+    }
+}
+
+/// A project on a specific provider, since IDs are only unique within one.
+/// Written as `<provider>:<ID>`, e.g. `modrinth:P7dR8mSH`, with the ID as
+/// the provider's website shows it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProjectRef {
+    pub provider: ProviderKind,
+    pub id: u64,
+}
+
+impl ProjectRef {
+    pub fn new(provider: ProviderKind, id: u64) -> Self {
+        Self { provider, id }
+    }
+}
+
+impl FromStr for ProjectRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (provider, id) = s
+            .split_once(':')
+            .ok_or_else(|| format!("'{}' not a valid project, expected <provider>:<ID>", s))?;
+        let provider = provider.parse::<ProviderKind>()?;
+        let id = provider
+            .provider()
+            .parse_id(id)
+            .ok_or_else(|| format!("'{}' not a valid {} ID", id, provider))?;
+        Ok(Self { provider, id })
+    }
+}
+
+impl Display for ProjectRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.provider.to_string().to_lowercase(),
+            self.provider.provider().format_id(self.id)
+        )
+    }
+}
+
+impl Serialize for ProjectRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProjectRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            /// Written back when every mod came from CurseForge.
+            Id(u64),
+            Ref(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Id(id) => Ok(Self::new(ProviderKind::CurseForge, id)),
+            Repr::Ref(s) => s.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// How stable a file is, from least to most stable, so that a file is
+/// acceptable when it is at least the minimum.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ReleaseType {
+    Alpha,
+    Beta,
+    #[default]
+    Release,
+}
+
+impl From<u8> for ReleaseType {
+    fn from(id: u8) -> Self {
+        match id {
+            1 => Self::Release,
+            2 => Self::Beta,
+            // Anything unknown is treated as the least stable.
+            _ => Self::Alpha,
+        }
+    }
+}
+
+impl FromStr for ReleaseType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "release" => Ok(Self::Release),
+            "beta" => Ok(Self::Beta),
+            "alpha" => Ok(Self::Alpha),
+
+            s => Err(format!("'{}' not a valid release type", s)),
+        }
+    }
+}
+
+impl Display for ReleaseType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match &self {
+                Self::Release => "release",
+                Self::Beta => "beta",
+                Self::Alpha => "alpha",
+            }
+        )
+    }
+}
+
+/// CurseForge sends release types as numbers.
+fn deserialize_release_type<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ReleaseType, D::Error> {
+    u8::deserialize(deserializer).map(ReleaseType::from)
+}
+
+/// The kind of project to search for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    Mods,
+    ResourcePacks,
+    Worlds,
+    Modpacks,
+}
+
+impl FromStr for Section {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mods" => Ok(Self::Mods),
+            "resourcepacks" => Ok(Self::ResourcePacks),
+            "worlds" => Ok(Self::Worlds),
+            "modpacks" => Ok(Self::Modpacks),
+
+            s => Err(format!("'{}' not a valid section", s)),
+        }
+    }
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match &self {
+                Self::Mods => "mods",
+                Self::ResourcePacks => "resource packs",
+                Self::Worlds => "worlds",
+                Self::Modpacks => "modpacks",
+            }
+        )
+    }
+}
+
+/// A category that projects of a section are filed under, along with its
+/// subcategories.
+#[derive(Clone, Debug, Serialize)]
+pub struct CategoryNode {
+    /// The CurseForge ID, or 0 on Modrinth, which identifies categories by
+    /// their slug.
+    pub id: u64,
+    pub name: String,
+    pub slug: String,
+    pub children: Vec<CategoryNode>,
+}
+
+impl CategoryNode {
+    /// Builds the category tree from a flat list of categories, each with
+    /// the ID of its parent. Categories whose parent isn't in the list are
+    /// at the top.
+    pub fn tree(flat: Vec<(CategoryNode, Option<u64>)>) -> Vec<CategoryNode> {
+        fn children_of(
+            parent: Option<u64>,
+            flat: &[(CategoryNode, Option<u64>)],
+            is_root: &dyn Fn(Option<u64>) -> bool,
+        ) -> Vec<CategoryNode> {
+            flat.iter()
+                .filter(|(_, p)| match parent {
+                    Some(_) => *p == parent,
+                    None => is_root(*p),
+                })
+                .map(|(node, _)| CategoryNode {
+                    children: children_of(Some(node.id), flat, is_root),
+                    ..node.clone()
+                })
+                .collect()
+        }
+
+        let ids = flat.iter().map(|(node, _)| node.id).collect::<Vec<_>>();
+        let is_root = |parent: Option<u64>| parent.is_none_or(|p| !ids.contains(&p));
+        children_of(None, &flat, &is_root)
+    }
+
+    /// Finds a category anywhere in the tree by its slug or name.
+    pub fn find<'a>(tree: &'a [CategoryNode], name: &str) -> Option<&'a CategoryNode> {
+        tree.iter().find_map(|node| {
+            if node.slug.eq_ignore_ascii_case(name) || node.name.eq_ignore_ascii_case(name) {
+                Some(node)
+            } else {
+                Self::find(&node.children, name)
+            }
+        })
+    }
+}
+
+/// What search results are limited to, besides the search text and the
+/// game version.
+#[derive(Clone, Debug)]
+pub struct Filters {
+    /// Only applies to mods.
+    pub mod_loader: ModLoader,
+    pub section: Section,
+    pub category: Option<CategoryNode>,
+    /// The name of one of the authors of the project.
+    pub author: Option<String>,
+}
+
+impl Filters {
+    pub fn mods(mod_loader: &ModLoader) -> Self {
+        Self {
+            mod_loader: mod_loader.clone(),
+            section: Section::Mods,
+            category: None,
+            author: None,
         }
     }
+
+    /// Whether the result is by the author, if one is asked for.
+    pub fn is_by_author(&self, result: &SearchResult) -> bool {
+        self.author.as_ref().is_none_or(|author| {
+            result
+                .authors
+                .iter()
+                .any(|a| a.name.eq_ignore_ascii_case(author))
+        })
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -74,7 +356,7 @@ impl Display for SortType {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchResult {
     pub authors: Vec<Author>,
@@ -86,30 +368,52 @@ pub struct SearchResult {
 
     #[serde(rename = "gameVersionLatestFiles")]
     pub game_files: Vec<GameFile>,
-    pub id: u32,
+    pub id: u64,
     pub name: String,
+    #[serde(default)]
+    pub slug: String,
     pub website_url: String,
 }
 
 impl SearchResult {
-    pub fn get_file_by_version(&self, version: &str) -> Option<&GameFile> {
-        self.game_files.iter().find(|&f| f.game_version == version)
+    pub fn get_file_by_version(
+        &self,
+        version: &str,
+        min_release: ReleaseType,
+    ) -> Option<&GameFile> {
+        self.get_file_by_version_and_loader(version, &ModLoader::Both, min_release)
+    }
+
+    /// The newest file for the version and loader that is at least as
+    /// stable as `min_release`, or the newest file of any release type if
+    /// there is none. Callers should check the release type of the file.
+    pub fn get_file_by_version_and_loader(
+        &self,
+        version: &str,
+        loader: &ModLoader,
+        min_release: ReleaseType,
+    ) -> Option<&GameFile> {
+        let files = self
+            .game_files
+            .iter()
+            .filter(|&f| f.game_version == version && f.is_for(loader));
+
+        files
+            .clone()
+            .filter(|f| f.file_type >= min_release)
+            .max_by_key(|f| f.project_file_id)
+            .or_else(|| files.max_by_key(|f| f.project_file_id))
     }
 
     pub fn author_names(&self) -> String {
-        // TODO: implement join() for Vec<Author>
         format!(
             "{} {}",
             self.authors
                 .iter()
                 .take(3)
-                .fold(String::new(), |mut a, c| {
-                    a.push_str(&c.name);
-                    a.push_str(", ");
-                    a
-                })
-                .strip_suffix(", ")
-                .expect("expected author string to end with a semicolon"),
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
             if self.authors.len() > 3 { "et al." } else { "" }
         )
     }
@@ -121,14 +425,14 @@ impl SearchResult {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Author {
     pub name: String,
     pub url: String,
-    pub id: u32,
+    pub id: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Category {
     pub category_id: u32,
@@ -142,44 +446,300 @@ impl Category {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GameFile {
     pub game_version: String,
-    pub project_file_id: u32,
+    pub project_file_id: u64,
     pub project_file_name: String,
-    pub file_type: u8,
+    #[serde(deserialize_with = "deserialize_release_type")]
+    pub file_type: ReleaseType,
+    #[serde(default)]
+    pub mod_loader: Option<u32>,
 }
 
-#[derive(Debug, Deserialize)]
+impl GameFile {
+    pub fn is_for(&self, loader: &ModLoader) -> bool {
+        match (loader, self.mod_loader) {
+            (ModLoader::Both, _) | (_, None) | (_, Some(0)) => true,
+            (ModLoader::Forge, Some(id)) => id == 1,
+            (ModLoader::Fabric, Some(id)) => id == 4,
+        }
+    }
+}
+
+/// One page of a longer listing.
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The position of the first item in the whole listing.
+    pub index: usize,
+    /// Where the following page starts, or `None` if this is the last one.
+    /// This is further than `items` reach when the provider filtered out
+    /// some of the listing itself.
+    pub next: Option<usize>,
+    /// How many items there are in the whole listing, if known.
+    pub total: Option<usize>,
+}
+
+impl<T> Page<T> {
+    /// Cuts a page out of a listing that was fetched all at once.
+    pub fn of(mut items: Vec<T>, index: usize, page_size: usize) -> Self {
+        let total = items.len();
+        items.truncate(index.saturating_add(page_size));
+        let items = items.split_off(index.min(total));
+        let end = index + items.len();
+        Self {
+            items,
+            index,
+            next: Some(end).filter(|&end| end < total),
+            total: Some(total),
+        }
+    }
+
+    /// Whether there may be items after this page.
+    pub fn has_more(&self) -> bool {
+        self.next.is_some()
+    }
+}
+
+/// A file as listed among every file of a project, without its
+/// dependencies.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectFile {
+    pub id: u64,
+    pub display_name: String,
+    pub file_name: String,
+    /// When the file was uploaded, as an RFC 3339 timestamp.
+    #[serde(rename = "fileDate")]
+    pub date: String,
+    #[serde(deserialize_with = "deserialize_release_type")]
+    pub release_type: ReleaseType,
+    /// The game versions the file is for, and on CurseForge also the mod
+    /// loaders.
+    #[serde(rename = "gameVersion")]
+    pub game_versions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ModInfo {
-    pub id: u32,
+    /// Not part of the file response, filled in from the project the file
+    /// was requested for.
+    #[serde(skip)]
+    pub project_id: u64,
+    /// Not part of the file response either, filled in by the provider the
+    /// file was requested from.
+    #[serde(skip)]
+    pub provider: ProviderKind,
+    pub id: u64,
     pub display_name: String,
     pub file_name: String,
+    /// The size of the file in bytes, or 0 if unknown.
+    #[serde(default)]
+    pub file_length: u64,
+    /// The CurseForge fingerprint of the file, or 0 if unknown. See
+    /// `crate::fingerprint`.
+    #[serde(default)]
+    pub package_fingerprint: u32,
+    /// Only Modrinth reports these, so they are filled in by its provider.
+    #[serde(skip)]
+    pub hashes: FileHashes,
+    #[serde(default, deserialize_with = "deserialize_release_type")]
+    pub release_type: ReleaseType,
+    /// When the file was uploaded, as an RFC 3339 timestamp, or empty if
+    /// unknown.
+    #[serde(default)]
+    pub file_date: String,
     pub download_url: String,
     pub dependencies: Vec<Dependency>,
 }
 
+/// Hex-encoded hashes of a file, each empty if unknown.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct FileHashes {
+    #[serde(default)]
+    pub sha1: String,
+    #[serde(default)]
+    pub sha512: String,
+}
+
 impl ModInfo {
-    pub fn hard_dependencies(&self) -> Vec<&Dependency> {
+    pub fn project(&self) -> ProjectRef {
+        ProjectRef::new(self.provider, self.project_id)
+    }
+
+    pub fn dependencies_of_type(&self, dep_type: DependencyType) -> Vec<&Dependency> {
         self.dependencies
             .iter()
-            .filter(|d| d.dep_type == Dependency::hard_id())
+            .filter(|d| d.dep_type == dep_type)
+            .collect()
+    }
+
+    /// Required dependencies, except for those that are bundled with the
+    /// file as embedded libraries.
+    pub fn hard_dependencies(&self) -> Vec<&Dependency> {
+        let embedded = self.dependencies_of_type(DependencyType::EmbeddedLibrary);
+        self.dependencies_of_type(DependencyType::Required)
+            .into_iter()
+            .filter(|d| !embedded.iter().any(|e| e.project() == d.project()))
             .collect()
     }
+
+    pub fn optional_dependencies(&self) -> Vec<&Dependency> {
+        self.dependencies_of_type(DependencyType::Optional)
+    }
+
+    pub fn embeds(&self, project: ProjectRef) -> bool {
+        self.dependencies_of_type(DependencyType::EmbeddedLibrary)
+            .iter()
+            .any(|d| d.project() == project)
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(from = "u32")]
+pub enum DependencyType {
+    EmbeddedLibrary,
+    Optional,
+    Required,
+    Tool,
+    Incompatible,
+    Include,
+    Other(u32),
+}
+
+impl From<u32> for DependencyType {
+    fn from(id: u32) -> Self {
+        match id {
+            1 => Self::EmbeddedLibrary,
+            2 => Self::Optional,
+            3 => Self::Required,
+            4 => Self::Tool,
+            5 => Self::Incompatible,
+            6 => Self::Include,
+            id => Self::Other(id),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Dependency {
-    pub addon_id: u32,
+    /// Not part of the file response, the provider the project is on.
+    /// Usually that of the file itself, unless the dependency has been
+    /// mapped to another provider by `crate::mapping`.
+    #[serde(skip)]
+    pub provider: ProviderKind,
+    pub addon_id: u64,
     #[serde(rename = "type")]
-    pub dep_type: u32,
+    pub dep_type: DependencyType,
 }
 
 impl Dependency {
-    pub fn hard_id() -> u32 {
-        3
+    pub fn project(&self) -> ProjectRef {
+        ProjectRef::new(self.provider, self.addon_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(id: u64, file_type: ReleaseType) -> GameFile {
+        GameFile {
+            game_version: "1.16.4".into(),
+            project_file_id: id,
+            project_file_name: format!("{}.jar", id),
+            file_type,
+            mod_loader: None,
+        }
+    }
+
+    #[test]
+    fn categories() {
+        let node = |id, name: &str| CategoryNode {
+            id,
+            name: name.into(),
+            slug: name.to_lowercase(),
+            children: vec![],
+        };
+        let tree = CategoryNode::tree(vec![
+            (node(412, "Technology"), Some(6)),
+            (node(415, "Energy"), Some(412)),
+            (node(421, "Library"), Some(6)),
+            (node(416, "Storage"), Some(412)),
+        ]);
+
+        let names = |nodes: &[CategoryNode]| nodes.iter().map(|n| n.id).collect::<Vec<_>>();
+        assert_eq!(names(&tree), vec![412, 421]);
+        assert_eq!(names(&tree[0].children), vec![415, 416]);
+        assert_eq!(
+            CategoryNode::find(&tree, "storage").map(|n| n.id),
+            Some(416)
+        );
+        assert_eq!(
+            CategoryNode::find(&tree, "Library").map(|n| n.id),
+            Some(421)
+        );
+        assert!(CategoryNode::find(&tree, "Magic").is_none());
+    }
+
+    #[test]
+    fn pages() {
+        let page = Page::of((0..5).collect(), 2, 2);
+        assert_eq!(page.items, vec![2, 3]);
+        assert_eq!(page.next, Some(4));
+        assert!(page.has_more());
+
+        let page = Page::of((0..5).collect(), 4, 2);
+        assert_eq!(page.items, vec![4]);
+        assert!(!page.has_more());
+
+        let page = Page::of((0..5).collect::<Vec<_>>(), 7, 2);
+        assert!(page.items.is_empty());
+        assert!(!page.has_more());
+    }
+
+    #[test]
+    fn release_types() {
+        let mut result = SearchResult {
+            authors: vec![],
+            description: String::new(),
+            categories: vec![],
+            game_files: vec![
+                file(1, ReleaseType::Release),
+                file(3, ReleaseType::Alpha),
+                file(2, ReleaseType::Beta),
+            ],
+            id: 0,
+            name: String::new(),
+            slug: String::new(),
+            website_url: String::new(),
+        };
+
+        let newest = |min| {
+            result
+                .get_file_by_version("1.16.4", min)
+                .map(|f| f.project_file_id)
+        };
+        assert_eq!(newest(ReleaseType::Release), Some(1));
+        assert_eq!(newest(ReleaseType::Beta), Some(2));
+        assert_eq!(newest(ReleaseType::Alpha), Some(3));
+
+        result.game_files.remove(0);
+        assert_eq!(
+            result
+                .get_file_by_version("1.16.4", ReleaseType::Release)
+                .map(|f| f.project_file_id),
+            Some(3)
+        );
+        assert_eq!(
+            result
+                .get_file_by_version("1.12.2", ReleaseType::Alpha)
+                .map(|f| f.project_file_id),
+            None
+        );
     }
 }
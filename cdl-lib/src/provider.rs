@@ -0,0 +1,79 @@
+mod curseforge;
+mod modrinth;
+
+use crate::models::{
+    CategoryNode, Filters, ModInfo, ModLoader, Page, ProjectFile, ProviderKind, ReleaseType,
+    SearchResult, Section, SortType,
+};
+use async_trait::async_trait;
+
+pub use curseforge::CurseForge;
+pub use modrinth::Modrinth;
+
+/// A site that mods can be searched for and downloaded from. Projects and
+/// files are identified by numeric IDs that are only unique within a
+/// provider. The dependencies and download URL of a file are part of the
+/// `ModInfo` a provider returns for it.
+#[async_trait]
+pub trait Provider: Sync {
+    fn kind(&self) -> ProviderKind;
+
+    /// Parses an ID the way the provider's website shows it.
+    fn parse_id(&self, id: &str) -> Option<u64>;
+
+    /// Formats an ID the way the provider's website shows it.
+    fn format_id(&self, id: u64) -> String;
+
+    /// Fetches `amount` search results starting at `index`, with the
+    /// following page starting at the returned page's `next`, which skips
+    /// over any results the provider filtered out itself.
+    async fn search(
+        &self,
+        query: &str,
+        game_version: &str,
+        filters: &Filters,
+        index: usize,
+        amount: u8,
+        sort_type: &SortType,
+    ) -> surf::Result<Page<SearchResult>>;
+
+    /// Fetches the categories of a section, to search by.
+    async fn get_categories(&self, section: Section) -> surf::Result<Vec<CategoryNode>>;
+
+    /// Fetches a single project by its ID.
+    async fn get_mod(&self, mod_id: u64) -> surf::Result<SearchResult>;
+
+    /// Finds a project by its ID or by exactly its slug, such as "jei".
+    async fn find_mod(
+        &self,
+        project: &str,
+        game_version: &str,
+    ) -> surf::Result<Option<SearchResult>>;
+
+    /// Fetches every file of a project, for any game version or mod loader,
+    /// newest first. Both providers only send them all at once.
+    async fn get_files(&self, mod_id: u64) -> surf::Result<Vec<ProjectFile>>;
+
+    /// Fetches a specific file of a project.
+    async fn get_file(&self, mod_id: u64, file_id: u64) -> surf::Result<ModInfo>;
+
+    /// Fetches the newest file of a project for the game version and mod
+    /// loader that is at least as stable as `min_release`, falling back to
+    /// the newest file of any release type, or `None` if there is no file.
+    async fn get_latest_file(
+        &self,
+        game_version: &str,
+        mod_loader: &ModLoader,
+        min_release: ReleaseType,
+        mod_id: u64,
+    ) -> surf::Result<Option<ModInfo>>;
+}
+
+impl ProviderKind {
+    pub fn provider(&self) -> &'static dyn Provider {
+        match self {
+            Self::CurseForge => &CurseForge,
+            Self::Modrinth => &Modrinth,
+        }
+    }
+}
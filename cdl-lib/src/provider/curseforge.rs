@@ -0,0 +1,206 @@
+use super::Provider;
+use crate::models::{
+    CategoryNode, Filters, ModInfo, ModLoader, Page, ProjectFile, ProviderKind, ReleaseType,
+    SearchResult, Section, SortType,
+};
+use crate::url;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+pub struct CurseForge;
+
+/// How many results are fetched at a time while filling a page of search
+/// results, which is the most CurseForge allows.
+const SEARCH_PAGE_SIZE: u8 = 50;
+
+/// How many times results are fetched for one page of search results at
+/// most, so that filters that hardly match anything don't go through every
+/// result there is. The page is then returned with what was found so far.
+const MAX_SEARCH_REQUESTS: usize = 5;
+
+/// Whether a search result passes the filters the API can't apply.
+fn matches(filters: &Filters, result: &SearchResult) -> bool {
+    let mod_loader = &filters.mod_loader;
+    let wrong_loader = filters.section == Section::Mods
+        && ((mod_loader == &ModLoader::Forge && result.is_fabric())
+            || (mod_loader == &ModLoader::Fabric && !result.is_fabric()));
+    !wrong_loader && filters.is_by_author(result)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Category {
+    id: u64,
+    name: String,
+    slug: String,
+    parent_game_category_id: Option<u64>,
+    root_game_category_id: Option<u64>,
+}
+
+#[async_trait]
+impl Provider for CurseForge {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::CurseForge
+    }
+
+    fn parse_id(&self, id: &str) -> Option<u64> {
+        id.parse().ok()
+    }
+
+    fn format_id(&self, id: u64) -> String {
+        id.to_string()
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        game_version: &str,
+        filters: &Filters,
+        index: usize,
+        amount: u8,
+        sort_type: &SortType,
+    ) -> surf::Result<Page<SearchResult>> {
+        // Neither the loader nor the author can be searched by, so results
+        // are filtered here and more are fetched until the page is full, or
+        // until `MAX_SEARCH_REQUESTS` have been made.
+        if amount == 0 {
+            return Ok(Page::of(vec![], index, 0));
+        }
+
+        let mut items = vec![];
+        let mut position = index;
+        for _ in 0..MAX_SEARCH_REQUESTS {
+            let url = url::search_url(
+                query,
+                game_version,
+                position,
+                SEARCH_PAGE_SIZE,
+                sort_type,
+                filters.category.as_ref().map_or(0, |c| c.id),
+                url::section_id(filters.section),
+            );
+            let results = surf::get(url).recv_json::<Vec<SearchResult>>().await?;
+            // CurseForge doesn't say how many results there are, only a
+            // page that isn't full shows that there are no more.
+            let exhausted = results.len() < SEARCH_PAGE_SIZE as usize;
+
+            let fetched = results.len();
+            for (i, result) in results.into_iter().enumerate() {
+                position += 1;
+                if !matches(filters, &result) {
+                    continue;
+                }
+
+                items.push(result);
+                if items.len() == amount as usize {
+                    let more = i + 1 < fetched || !exhausted;
+                    return Ok(Page {
+                        items,
+                        index,
+                        next: Some(position).filter(|_| more),
+                        // How many results match is never known.
+                        total: None,
+                    });
+                }
+            }
+
+            if exhausted {
+                return Ok(Page {
+                    items,
+                    index,
+                    next: None,
+                    total: None,
+                });
+            }
+        }
+
+        Ok(Page {
+            items,
+            index,
+            next: Some(position),
+            total: None,
+        })
+    }
+
+    async fn get_categories(&self, section: Section) -> surf::Result<Vec<CategoryNode>> {
+        let section = url::section_id(section);
+        let categories = surf::get(url::categories_url())
+            .recv_json::<Vec<Category>>()
+            .await?
+            .into_iter()
+            .filter(|c| {
+                c.root_game_category_id == Some(section)
+                    || c.parent_game_category_id == Some(section)
+            })
+            .map(|c| {
+                let node = CategoryNode {
+                    id: c.id,
+                    name: c.name,
+                    slug: c.slug,
+                    children: vec![],
+                };
+                (node, c.parent_game_category_id)
+            })
+            .collect();
+        Ok(CategoryNode::tree(categories))
+    }
+
+    async fn get_mod(&self, mod_id: u64) -> surf::Result<SearchResult> {
+        surf::get(url::mod_url(mod_id)).recv_json().await
+    }
+
+    async fn find_mod(
+        &self,
+        project: &str,
+        game_version: &str,
+    ) -> surf::Result<Option<SearchResult>> {
+        if let Some(id) = self.parse_id(project) {
+            return Ok(Some(self.get_mod(id).await?));
+        }
+
+        let url = url::search_url(
+            project,
+            game_version,
+            0,
+            50,
+            &SortType::Popularity,
+            0,
+            url::section_id(Section::Mods),
+        );
+        let results = surf::get(url).recv_json::<Vec<SearchResult>>().await?;
+        Ok(results.into_iter().find(|r| r.slug == project))
+    }
+
+    async fn get_files(&self, mod_id: u64) -> surf::Result<Vec<ProjectFile>> {
+        // CurseForge sends every file at once, in no particular order.
+        let mut files = surf::get(url::files_url(mod_id))
+            .recv_json::<Vec<ProjectFile>>()
+            .await?;
+        files.sort_by(|a, b| b.date.cmp(&a.date));
+        Ok(files)
+    }
+
+    async fn get_file(&self, mod_id: u64, file_id: u64) -> surf::Result<ModInfo> {
+        let mut file = surf::get(url::info_url(mod_id, file_id))
+            .recv_json::<ModInfo>()
+            .await?;
+        file.project_id = mod_id;
+        file.provider = ProviderKind::CurseForge;
+        Ok(file)
+    }
+
+    async fn get_latest_file(
+        &self,
+        game_version: &str,
+        mod_loader: &ModLoader,
+        min_release: ReleaseType,
+        mod_id: u64,
+    ) -> surf::Result<Option<ModInfo>> {
+        let result = self.get_mod(mod_id).await?;
+
+        match result.get_file_by_version_and_loader(game_version, mod_loader, min_release) {
+            Some(file) => Ok(Some(self.get_file(result.id, file.project_file_id).await?)),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use super::Provider;
+use crate::models::{
+    Author, Category, CategoryNode, Dependency, DependencyType, FileHashes, Filters, ModInfo,
+    ModLoader, Page, ProjectFile, ProviderKind, ReleaseType, SearchResult, Section, SortType,
+};
+use crate::url;
+use async_trait::async_trait;
+use serde::{de, Deserialize, Deserializer};
+use surf::{RequestBuilder, StatusCode, Url};
+
+/// Modrinth identifies everything by base62 strings, which are just
+/// encoded numbers and are decoded as such to fit in with CurseForge.
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+fn decode(id: &str) -> Option<u64> {
+    if id.is_empty() {
+        return None;
+    }
+
+    id.bytes().try_fold(0u64, |n, b| {
+        let digit = BASE62.iter().position(|&c| c == b)? as u64;
+        n.checked_mul(62)?.checked_add(digit)
+    })
+}
+
+fn encode(mut id: u64) -> String {
+    let mut digits = vec![];
+    loop {
+        digits.push(BASE62[(id % 62) as usize]);
+        id /= 62;
+        if id == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    String::from_utf8(digits).unwrap_or_default()
+}
+
+fn deserialize_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let id = String::deserialize(deserializer)?;
+    decode(&id).ok_or_else(|| de::Error::custom(format!("'{}' not a valid ID", id)))
+}
+
+fn invalid_id(id: &str) -> surf::Error {
+    surf::Error::from_str(
+        StatusCode::UnprocessableEntity,
+        format!("'{}' not a valid Modrinth ID", id),
+    )
+}
+
+/// The project type of the section, or an error if Modrinth has none.
+fn project_type(section: Section) -> surf::Result<&'static str> {
+    url::modrinth_project_type(section).ok_or_else(|| {
+        surf::Error::from_str(StatusCode::NotFound, format!("Modrinth has no {}", section))
+    })
+}
+
+fn get(url: Url) -> RequestBuilder {
+    // Modrinth asks that every client identifies itself.
+    surf::get(url).header("User-Agent", concat!("cdl/", env!("CARGO_PKG_VERSION")))
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    hits: Vec<Hit>,
+    total_hits: usize,
+}
+
+#[derive(Deserialize)]
+struct Hit {
+    #[serde(deserialize_with = "deserialize_id")]
+    project_id: u64,
+    slug: String,
+    title: String,
+    description: String,
+    author: String,
+    #[serde(default = "default_project_type")]
+    project_type: String,
+    #[serde(default)]
+    categories: Vec<String>,
+}
+
+fn default_project_type() -> String {
+    "mod".into()
+}
+
+#[derive(Deserialize)]
+struct CategoryTag {
+    name: String,
+    project_type: String,
+}
+
+#[derive(Deserialize)]
+struct Project {
+    #[serde(deserialize_with = "deserialize_id")]
+    id: u64,
+    slug: String,
+    title: String,
+    description: String,
+    #[serde(default = "default_project_type")]
+    project_type: String,
+    #[serde(default)]
+    categories: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    #[serde(deserialize_with = "deserialize_id")]
+    id: u64,
+    #[serde(deserialize_with = "deserialize_id")]
+    project_id: u64,
+    name: String,
+    #[serde(default)]
+    version_type: String,
+    #[serde(default)]
+    date_published: String,
+    #[serde(default)]
+    game_versions: Vec<String>,
+    #[serde(default)]
+    loaders: Vec<String>,
+    files: Vec<VersionFile>,
+    #[serde(default)]
+    dependencies: Vec<VersionDependency>,
+}
+
+#[derive(Deserialize)]
+struct VersionFile {
+    url: String,
+    filename: String,
+    #[serde(default)]
+    primary: bool,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    hashes: FileHashes,
+}
+
+#[derive(Deserialize)]
+struct VersionDependency {
+    version_id: Option<String>,
+    project_id: Option<String>,
+    dependency_type: String,
+}
+
+fn categories(names: Vec<String>) -> Vec<Category> {
+    names
+        .into_iter()
+        .map(|name| Category {
+            category_id: 0,
+            name,
+            url: String::new(),
+        })
+        .collect()
+}
+
+impl From<Hit> for SearchResult {
+    fn from(hit: Hit) -> Self {
+        Self {
+            authors: vec![Author {
+                url: format!("https://modrinth.com/user/{}", hit.author),
+                name: hit.author,
+                id: 0,
+            }],
+            description: hit.description,
+            categories: categories(hit.categories),
+            game_files: vec![],
+            id: hit.project_id,
+            name: hit.title,
+            website_url: format!("https://modrinth.com/{}/{}", hit.project_type, hit.slug),
+            slug: hit.slug,
+        }
+    }
+}
+
+impl From<Project> for SearchResult {
+    fn from(project: Project) -> Self {
+        Self {
+            authors: vec![],
+            description: project.description,
+            categories: categories(project.categories),
+            game_files: vec![],
+            id: project.id,
+            name: project.title,
+            website_url: format!(
+                "https://modrinth.com/{}/{}",
+                project.project_type, project.slug
+            ),
+            slug: project.slug,
+        }
+    }
+}
+
+impl From<Version> for ProjectFile {
+    fn from(version: Version) -> Self {
+        let file_name = match version.files.iter().find(|f| f.primary) {
+            Some(file) => file.filename.clone(),
+            None => version
+                .files
+                .first()
+                .map(|f| f.filename.clone())
+                .unwrap_or_default(),
+        };
+
+        Self {
+            id: version.id,
+            display_name: version.name,
+            file_name,
+            date: version.date_published,
+            release_type: version.version_type.parse().unwrap_or_default(),
+            game_versions: version
+                .game_versions
+                .into_iter()
+                .chain(version.loaders)
+                .collect(),
+        }
+    }
+}
+
+pub struct Modrinth;
+
+impl Modrinth {
+    async fn get_version(&self, version: &str) -> surf::Result<Version> {
+        get(url::modrinth_version_url(version)).recv_json().await
+    }
+
+    /// Converts a version to a file, looking up the project of every
+    /// dependency that only names a version.
+    async fn to_info(&self, version: Version) -> surf::Result<ModInfo> {
+        let file = match version.files.iter().find(|f| f.primary) {
+            Some(file) => file,
+            None => version.files.first().ok_or_else(|| {
+                surf::Error::from_str(
+                    StatusCode::NotFound,
+                    format!("{} has no files", version.name),
+                )
+            })?,
+        };
+
+        let mut dependencies = vec![];
+        for dep in &version.dependencies {
+            let addon_id = match (&dep.project_id, &dep.version_id) {
+                (Some(project), _) => decode(project).ok_or_else(|| invalid_id(project))?,
+                (None, Some(version)) => self.get_version(version).await?.project_id,
+                // Dependencies on files that aren't on Modrinth at all.
+                (None, None) => continue,
+            };
+            let dep_type = match dep.dependency_type.as_str() {
+                "required" => DependencyType::Required,
+                "optional" => DependencyType::Optional,
+                "incompatible" => DependencyType::Incompatible,
+                "embedded" => DependencyType::EmbeddedLibrary,
+                _ => DependencyType::Other(0),
+            };
+            dependencies.push(Dependency {
+                provider: ProviderKind::Modrinth,
+                addon_id,
+                dep_type,
+            });
+        }
+
+        Ok(ModInfo {
+            project_id: version.project_id,
+            provider: ProviderKind::Modrinth,
+            id: version.id,
+            display_name: version.name.clone(),
+            file_name: file.filename.clone(),
+            file_length: file.size,
+            package_fingerprint: 0,
+            hashes: file.hashes.clone(),
+            release_type: version.version_type.parse().unwrap_or_default(),
+            file_date: version.date_published.clone(),
+            download_url: file.url.clone(),
+            dependencies,
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for Modrinth {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Modrinth
+    }
+
+    fn parse_id(&self, id: &str) -> Option<u64> {
+        decode(id)
+    }
+
+    fn format_id(&self, id: u64) -> String {
+        encode(id)
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        game_version: &str,
+        filters: &Filters,
+        index: usize,
+        amount: u8,
+        sort_type: &SortType,
+    ) -> surf::Result<Page<SearchResult>> {
+        project_type(filters.section)?;
+
+        let url = url::modrinth_search_url(query, game_version, index, amount, sort_type, filters);
+        let response = get(url).recv_json::<SearchResponse>().await?;
+        let end = index + response.hits.len();
+        let total = response.total_hits;
+        Ok(Page {
+            items: response.hits.into_iter().map(SearchResult::from).collect(),
+            index,
+            next: Some(end).filter(|&end| end > index && end < total),
+            total: Some(total),
+        })
+    }
+
+    async fn get_categories(&self, section: Section) -> surf::Result<Vec<CategoryNode>> {
+        let project_type = project_type(section)?;
+
+        // Modrinth's categories are all at the top, and named by their slug.
+        let tags = get(url::modrinth_categories_url())
+            .recv_json::<Vec<CategoryTag>>()
+            .await?;
+        Ok(tags
+            .into_iter()
+            .filter(|tag| tag.project_type == project_type)
+            .map(|tag| CategoryNode {
+                id: 0,
+                slug: tag.name.clone(),
+                name: tag.name,
+                children: vec![],
+            })
+            .collect())
+    }
+
+    async fn get_mod(&self, mod_id: u64) -> surf::Result<SearchResult> {
+        let project = get(url::modrinth_project_url(&encode(mod_id)))
+            .recv_json::<Project>()
+            .await?;
+        Ok(project.into())
+    }
+
+    async fn find_mod(
+        &self,
+        project: &str,
+        _game_version: &str,
+    ) -> surf::Result<Option<SearchResult>> {
+        // Modrinth looks projects up by either.
+        let mut response = get(url::modrinth_project_url(project)).await?;
+        if response.status() == StatusCode::NotFound {
+            return Ok(None);
+        }
+
+        let project = response.body_json::<Project>().await?;
+        Ok(Some(project.into()))
+    }
+
+    async fn get_files(&self, mod_id: u64) -> surf::Result<Vec<ProjectFile>> {
+        // Modrinth sends every version at once, newest first.
+        let versions = get(url::modrinth_all_versions_url(&encode(mod_id)))
+            .recv_json::<Vec<Version>>()
+            .await?;
+        Ok(versions.into_iter().map(ProjectFile::from).collect())
+    }
+
+    async fn get_file(&self, mod_id: u64, file_id: u64) -> surf::Result<ModInfo> {
+        let version = self.get_version(&encode(file_id)).await?;
+        if version.project_id != mod_id {
+            return Err(surf::Error::from_str(
+                StatusCode::NotFound,
+                format!("{} is not a file of {}", encode(file_id), encode(mod_id)),
+            ));
+        }
+
+        self.to_info(version).await
+    }
+
+    async fn get_latest_file(
+        &self,
+        game_version: &str,
+        mod_loader: &ModLoader,
+        min_release: ReleaseType,
+        mod_id: u64,
+    ) -> surf::Result<Option<ModInfo>> {
+        let url = url::modrinth_versions_url(&encode(mod_id), game_version, mod_loader);
+        let mut versions = get(url).recv_json::<Vec<Version>>().await?;
+
+        // Versions come newest first.
+        let stable = versions
+            .iter()
+            .position(|v| v.version_type.parse::<ReleaseType>().unwrap_or_default() >= min_release);
+        match stable {
+            Some(i) => Ok(Some(self.to_info(versions.swap_remove(i)).await?)),
+            None if !versions.is_empty() => Ok(Some(self.to_info(versions.remove(0)).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids() {
+        assert_eq!(decode("10"), Some(62));
+        assert_eq!(decode("AANobbMI").map(encode), Some("AANobbMI".into()));
+        assert_eq!(encode(0), "0");
+        assert_eq!(decode(""), None);
+        assert_eq!(decode("not-an-id"), None);
+    }
+}
@@ -0,0 +1,455 @@
+use crate::instance::Instance;
+use crate::mapping::Mapper;
+use crate::models::{DependencyType, ModInfo, ModLoader, ProjectRef, ProviderKind, ReleaseType};
+use futures::{stream, StreamExt, TryStreamExt};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+#[derive(Debug)]
+pub struct PlannedMod {
+    pub info: ModInfo,
+    pub explicit: bool,
+    /// Whether the file was pinned rather than the latest one.
+    pub pinned: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// Mods in install order, dependencies before their dependents.
+    pub mods: Vec<PlannedMod>,
+    /// Every dependency cycle found, as the projects that form it.
+    pub cycles: Vec<Vec<ProjectRef>>,
+    /// Projects that have no file for the requested game version.
+    pub missing: Vec<ProjectRef>,
+    /// Projects whose planned file is less stable than the minimum release
+    /// type, because they have no file that is stable enough.
+    pub unstable: Vec<ProjectRef>,
+    /// Optional dependencies that are neither planned nor embedded in a
+    /// planned mod, along with the project that wants each of them.
+    pub optional: Vec<(ProjectRef, ProjectRef)>,
+}
+
+/// Two mods that declare themselves incompatible with each other, where the
+/// first is the one that declared it.
+#[derive(Debug)]
+pub struct Conflict {
+    pub project: ProjectRef,
+    pub name: String,
+    pub other: ProjectRef,
+    pub other_name: String,
+}
+
+impl Plan {
+    /// Finds every incompatibility between the planned mods, and between
+    /// the planned mods and those already installed in `instance`.
+    pub fn conflicts(&self, instance: &Instance) -> Vec<Conflict> {
+        let mut conflicts = Vec::<Conflict>::new();
+        let mut push = |conflict: Conflict| {
+            let duplicate = conflicts.iter().any(|c| {
+                (c.project, c.other) == (conflict.project, conflict.other)
+                    || (c.project, c.other) == (conflict.other, conflict.project)
+            });
+            if !duplicate {
+                conflicts.push(conflict);
+            }
+        };
+
+        for planned in &self.mods {
+            let info = &planned.info;
+            for dep in info.dependencies_of_type(DependencyType::Incompatible) {
+                let other = dep.project();
+                let other_name = match self.mods.iter().find(|m| m.info.project() == other) {
+                    Some(other) => other.info.display_name.clone(),
+                    None => match instance.get(other) {
+                        Some(other) => other.name.clone(),
+                        None => continue,
+                    },
+                };
+
+                push(Conflict {
+                    project: info.project(),
+                    name: info.display_name.clone(),
+                    other,
+                    other_name,
+                });
+            }
+        }
+
+        for locked in &instance.lock.mods {
+            for &project in &locked.incompatible {
+                if let Some(other) = self.mods.iter().find(|m| m.info.project() == project) {
+                    push(Conflict {
+                        project: locked.project(),
+                        name: locked.name.clone(),
+                        other: project,
+                        other_name: other.info.display_name.clone(),
+                    });
+                }
+            }
+        }
+
+        conflicts
+    }
+}
+
+/// Incrementally builds the dependency graph of a set of projects, which
+/// may come from different providers, fetching every project exactly once.
+/// Dependencies are taken from the preferred provider wherever the same
+/// project is on it, so that mods share a single copy of every library.
+pub struct Resolver<'a> {
+    preferred: ProviderKind,
+    game_version: &'a str,
+    mod_loader: &'a ModLoader,
+    min_release: ReleaseType,
+    jobs: usize,
+    mapper: Mapper<'a>,
+    roots: Vec<ProjectRef>,
+    pins: HashMap<ProjectRef, u64>,
+    nodes: HashMap<ProjectRef, ModInfo>,
+    missing: Vec<ProjectRef>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(
+        preferred: ProviderKind,
+        game_version: &'a str,
+        mod_loader: &'a ModLoader,
+        min_release: ReleaseType,
+        jobs: usize,
+    ) -> Self {
+        Self {
+            preferred,
+            game_version,
+            mod_loader,
+            min_release,
+            jobs,
+            mapper: Mapper::new(game_version, mod_loader),
+            roots: vec![],
+            pins: HashMap::new(),
+            nodes: HashMap::new(),
+            missing: vec![],
+        }
+    }
+
+    /// Makes the resolver use the given file of a project, rather than the
+    /// latest one for the game version. Must be called before the project
+    /// is added.
+    pub fn pin(&mut self, project: ProjectRef, file_id: u64) {
+        self.pins.insert(project, file_id);
+    }
+
+    /// Pins the file of every mod in the instance that was installed
+    /// pinned, so that resolving it again keeps that file.
+    pub fn pin_installed(&mut self, instance: &Instance) {
+        for locked in instance.lock.mods.iter().filter(|m| m.pinned) {
+            self.pin(locked.project(), locked.file_id);
+        }
+    }
+
+    /// Adds `roots` and everything they require to the graph, with at most
+    /// `jobs` requests in flight.
+    pub async fn add(&mut self, roots: &[ProjectRef]) -> surf::Result<()> {
+        let preferred = self.preferred;
+        let game_version = self.game_version;
+        let mod_loader = self.mod_loader;
+        let min_release = self.min_release;
+        let mapper = &self.mapper;
+        let pins = &self.pins;
+
+        let mut frontier = vec![];
+        for &id in roots {
+            if !self.roots.contains(&id) {
+                self.roots.push(id);
+            }
+            if !self.nodes.contains_key(&id)
+                && !self.missing.contains(&id)
+                && !frontier.contains(&id)
+            {
+                frontier.push(id);
+            }
+        }
+
+        while !frontier.is_empty() {
+            let files = stream::iter(&frontier)
+                .map(|&id| async move {
+                    let provider = id.provider.provider();
+                    let file = match pins.get(&id) {
+                        Some(&file_id) => Some(provider.get_file(id.id, file_id).await?),
+                        None => {
+                            provider
+                                .get_latest_file(game_version, mod_loader, min_release, id.id)
+                                .await?
+                        }
+                    };
+                    let file = match file {
+                        Some(mut file) => {
+                            mapper.map_dependencies(&mut file, preferred).await?;
+                            Some(file)
+                        }
+                        None => None,
+                    };
+                    Ok::<_, surf::Error>((id, file))
+                })
+                .buffered(self.jobs.max(1))
+                .try_collect::<Vec<_>>()
+                .await?;
+
+            let mut next = vec![];
+            for (id, file) in files {
+                match file {
+                    Some(file) => {
+                        for dep in file.hard_dependencies() {
+                            let dep = dep.project();
+                            if !self.nodes.contains_key(&dep)
+                                && !self.missing.contains(&dep)
+                                && !frontier.contains(&dep)
+                                && !next.contains(&dep)
+                            {
+                                next.push(dep);
+                            }
+                        }
+                        self.nodes.insert(id, file);
+                    }
+                    None => self.missing.push(id),
+                }
+            }
+
+            frontier = next;
+        }
+
+        Ok(())
+    }
+
+    pub fn plan(&self) -> Plan {
+        let edges = self
+            .nodes
+            .iter()
+            .map(|(&id, file)| {
+                let deps = file
+                    .hard_dependencies()
+                    .iter()
+                    .map(|d| d.project())
+                    .collect();
+                (id, deps)
+            })
+            .collect();
+        let (order, cycles) = order(&edges, &self.roots);
+
+        let mods = order
+            .into_iter()
+            .filter_map(|id| self.nodes.get(&id))
+            .map(|info| PlannedMod {
+                info: info.clone(),
+                explicit: self.roots.contains(&info.project()),
+                pinned: self.pins.contains_key(&info.project()),
+            })
+            .collect::<Vec<_>>();
+
+        let mut optional = vec![];
+        for planned in &mods {
+            for dep in planned.info.optional_dependencies() {
+                let dep = dep.project();
+                if !self.nodes.contains_key(&dep)
+                    && !mods.iter().any(|m| m.info.embeds(dep))
+                    && !optional.iter().any(|&(id, _)| id == dep)
+                {
+                    optional.push((dep, planned.info.project()));
+                }
+            }
+        }
+
+        let unstable = mods
+            .iter()
+            .map(|m| m.info.project())
+            .filter(|project| {
+                !self.pins.contains_key(project)
+                    && self.nodes[project].release_type < self.min_release
+            })
+            .collect();
+
+        Plan {
+            mods,
+            cycles,
+            missing: self.missing.clone(),
+            unstable,
+            optional,
+        }
+    }
+}
+
+/// Resolves `roots` along with everything they require in one go.
+pub async fn resolve(
+    preferred: ProviderKind,
+    game_version: &str,
+    mod_loader: &ModLoader,
+    min_release: ReleaseType,
+    roots: &[ProjectRef],
+    jobs: usize,
+) -> surf::Result<Plan> {
+    let mut resolver = Resolver::new(preferred, game_version, mod_loader, min_release, jobs);
+    resolver.add(roots).await?;
+    Ok(resolver.plan())
+}
+
+/// Orders the graph so that every node comes after its dependencies,
+/// visiting the roots in the given order. Edges that would close a cycle
+/// are skipped, and the cycle is reported instead.
+fn order<K: Copy + Eq + Hash>(edges: &HashMap<K, Vec<K>>, roots: &[K]) -> (Vec<K>, Vec<Vec<K>>) {
+    fn visit<K: Copy + Eq + Hash>(
+        id: K,
+        edges: &HashMap<K, Vec<K>>,
+        stack: &mut Vec<K>,
+        done: &mut HashSet<K>,
+        order: &mut Vec<K>,
+        cycles: &mut Vec<Vec<K>>,
+    ) {
+        if done.contains(&id) {
+            return;
+        }
+
+        if let Some(pos) = stack.iter().position(|&s| s == id) {
+            cycles.push(stack[pos..].to_vec());
+            return;
+        }
+
+        let deps = match edges.get(&id) {
+            Some(deps) => deps,
+            None => return,
+        };
+
+        stack.push(id);
+        for &dep in deps {
+            visit(dep, edges, stack, done, order, cycles);
+        }
+        stack.pop();
+
+        done.insert(id);
+        order.push(id);
+    }
+
+    let mut stack = vec![];
+    let mut done = HashSet::new();
+    let mut order = vec![];
+    let mut cycles = vec![];
+
+    for &root in roots {
+        visit(root, edges, &mut stack, &mut done, &mut order, &mut cycles);
+    }
+
+    (order, cycles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{dependencies, info, locked, refs};
+    use crate::instance::LockedMod;
+    use std::path::Path;
+
+    fn planned(project_id: u64, incompatible: &[u64]) -> PlannedMod {
+        PlannedMod {
+            info: ModInfo {
+                dependencies: dependencies(incompatible, DependencyType::Incompatible),
+                ..info(project_id, project_id * 10)
+            },
+            explicit: true,
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn pins_installed() {
+        let mut instance = Instance::new(
+            Path::new("."),
+            "1.16.4",
+            &ModLoader::Forge,
+            ProviderKind::CurseForge,
+        );
+        instance.lock.mods = vec![
+            LockedMod {
+                pinned: true,
+                ..locked(1, true, &[])
+            },
+            locked(2, true, &[]),
+        ];
+
+        let loader = ModLoader::Forge;
+        let mut resolver = Resolver::new(
+            ProviderKind::CurseForge,
+            "1.16.4",
+            &loader,
+            ReleaseType::Release,
+            1,
+        );
+        resolver.pin_installed(&instance);
+        assert_eq!(resolver.pins.get(&refs(&[1])[0]), Some(&10));
+        assert_eq!(resolver.pins.get(&refs(&[2])[0]), None);
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut instance = Instance::new(
+            Path::new("."),
+            "1.16.4",
+            &ModLoader::Forge,
+            ProviderKind::CurseForge,
+        );
+        instance.lock.mods = vec![
+            LockedMod {
+                incompatible: refs(&[3]),
+                ..locked(5, true, &[])
+            },
+            locked(6, true, &[]),
+        ];
+
+        let plan = Plan {
+            mods: vec![
+                // Declared in one direction only.
+                planned(1, &[2]),
+                planned(2, &[]),
+                // Declared in both directions.
+                planned(3, &[4]),
+                planned(4, &[3]),
+                // With an installed mod, declared by either of them, and
+                // with a project that is neither planned nor installed.
+                planned(7, &[6, 8]),
+            ],
+            ..Plan::default()
+        };
+
+        let pairs = plan
+            .conflicts(&instance)
+            .iter()
+            .map(|c| (c.project.id, c.other.id))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(1, 2), (3, 4), (7, 6), (5, 3)]);
+
+        let conflict = &plan.conflicts(&instance)[3];
+        assert_eq!(
+            (conflict.name.as_str(), conflict.other_name.as_str()),
+            ("5", "3")
+        );
+    }
+
+    #[test]
+    fn dependencies_first() {
+        let edges = vec![(1, vec![2, 3]), (2, vec![3]), (3, vec![]), (4, vec![3])]
+            .into_iter()
+            .collect();
+
+        let (order, cycles) = order(&edges, &[1, 4]);
+        assert_eq!(order, vec![3, 2, 1, 4]);
+        assert!(cycles.is_empty());
+    }
+
+    #[test]
+    fn cycles() {
+        let edges = vec![(1, vec![2]), (2, vec![3]), (3, vec![1]), (4, vec![4])]
+            .into_iter()
+            .collect();
+
+        let (order, cycles) = order(&edges, &[1, 4]);
+        assert_eq!(order, vec![3, 2, 1, 4]);
+        assert_eq!(cycles, vec![vec![1, 2, 3], vec![4]]);
+    }
+}
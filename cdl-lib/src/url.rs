@@ -1,31 +1,214 @@
-use crate::models::SortType;
+use crate::models::{Filters, ModLoader, Section, SortType};
+use surf::Url;
 
 const BASE_URL: &str = "https://addons-ecs.forgesvc.net/api/v2/addon";
+const CATEGORY_URL: &str = "https://addons-ecs.forgesvc.net/api/v2/category";
 
-pub fn search_url(query: &str, game_version: &str, amount: u8, sort_type: &SortType) -> String {
-    format!(
-        "{base}/search?categoryId={category_id}&gameId={game_id}&gameVersion={game_version}&index={index}&pageSize={page_size}&searchFilter={search_filter}&sectionId={section_id}&sort={sort}", 
-        base          = BASE_URL,
-        category_id   = 0,
-        game_id       = 432,
-        game_version  = game_version,
-        index         = 0,
-        page_size     = amount,
-        search_filter = query,
-        section_id    = 6,
-        sort          = sort_type,
+/// Appends every part of `path` to `base` as a segment of its own and adds
+/// the query, percent-encoding both, so that callers can pass anything a
+/// user typed.
+fn build(base: &str, path: &[&str], query: &[(&str, &str)]) -> Url {
+    let mut url = Url::parse(base).expect("base URLs are valid");
+    url.path_segments_mut()
+        .expect("base URLs have a path")
+        .extend(path);
+    if !query.is_empty() {
+        url.query_pairs_mut().extend_pairs(query);
+    }
+    url
+}
+
+/// The ID CurseForge files projects of the section under, which is also the
+/// parent of the section's top categories.
+pub fn section_id(section: Section) -> u64 {
+    match section {
+        Section::Mods => 6,
+        Section::ResourcePacks => 12,
+        Section::Worlds => 17,
+        Section::Modpacks => 4471,
+    }
+}
+
+/// `category_id` 0 searches every category of the section.
+pub fn search_url(
+    query: &str,
+    game_version: &str,
+    index: usize,
+    amount: u8,
+    sort_type: &SortType,
+    category_id: u64,
+    section_id: u64,
+) -> Url {
+    build(
+        BASE_URL,
+        &["search"],
+        &[
+            ("categoryId", &category_id.to_string()),
+            ("gameId", "432"),
+            ("gameVersion", game_version),
+            ("index", &index.to_string()),
+            ("pageSize", &amount.to_string()),
+            ("searchFilter", query),
+            ("sectionId", &section_id.to_string()),
+            ("sort", &sort_type.to_string()),
+        ],
+    )
+}
+
+pub fn mod_url(mod_id: u64) -> Url {
+    build(BASE_URL, &[&mod_id.to_string()], &[])
+}
+
+pub fn files_url(mod_id: u64) -> Url {
+    build(BASE_URL, &[&mod_id.to_string(), "files"], &[])
+}
+
+/// Every category of every game and section.
+pub fn categories_url() -> Url {
+    build(CATEGORY_URL, &[], &[])
+}
+
+pub fn info_url(mod_id: u64, file_id: u64) -> Url {
+    build(
+        BASE_URL,
+        &[&mod_id.to_string(), "file", &file_id.to_string()],
+        &[],
+    )
+}
+
+const MODRINTH_URL: &str = "https://api.modrinth.com/v2";
+
+/// The facet that limits Modrinth results to mods for `mod_loader`, where
+/// facets in the same list are OR'ed together.
+fn modrinth_loader_facet(mod_loader: &ModLoader) -> &'static str {
+    match mod_loader {
+        ModLoader::Forge => r#"["categories:forge"]"#,
+        ModLoader::Fabric => r#"["categories:fabric"]"#,
+        ModLoader::Both => r#"["categories:forge","categories:fabric"]"#,
+    }
+}
+
+fn modrinth_loaders(mod_loader: &ModLoader) -> &'static str {
+    match mod_loader {
+        ModLoader::Forge => r#"["forge"]"#,
+        ModLoader::Fabric => r#"["fabric"]"#,
+        ModLoader::Both => r#"["forge","fabric"]"#,
+    }
+}
+
+/// The project type Modrinth gives projects of the section, if it has any.
+pub fn modrinth_project_type(section: Section) -> Option<&'static str> {
+    match section {
+        Section::Mods => Some("mod"),
+        Section::ResourcePacks => Some("resourcepack"),
+        Section::Worlds => None,
+        Section::Modpacks => Some("modpack"),
+    }
+}
+
+/// A string as a JSON string, as Modrinth takes its lists in JSON.
+fn json_string(s: &str) -> String {
+    format!(r#""{}""#, s.replace('\\', r"\\").replace('"', r#"\""#))
+}
+
+/// Sections Modrinth has no project type for search as mods; check
+/// `modrinth_project_type` first.
+pub fn modrinth_search_url(
+    query: &str,
+    game_version: &str,
+    offset: usize,
+    amount: u8,
+    sort_type: &SortType,
+    filters: &Filters,
+) -> Url {
+    let facet =
+        |name: &str, value: &str| format!("[{}]", json_string(&format!("{}:{}", name, value)));
+    let mut facets = vec![
+        facet(
+            "project_type",
+            modrinth_project_type(filters.section).unwrap_or("mod"),
+        ),
+        facet("versions", game_version),
+    ];
+    if filters.section == Section::Mods {
+        facets.push(modrinth_loader_facet(&filters.mod_loader).into());
+    }
+    if let Some(category) = &filters.category {
+        facets.push(facet("categories", &category.slug));
+    }
+    if let Some(author) = &filters.author {
+        facets.push(facet("author", author));
+    }
+
+    build(
+        MODRINTH_URL,
+        &["search"],
+        &[
+            ("query", query),
+            ("offset", &offset.to_string()),
+            ("limit", &amount.to_string()),
+            // Modrinth can't sort by name, so that falls back to relevance.
+            (
+                "index",
+                match sort_type {
+                    SortType::TotalDownloads => "downloads",
+                    SortType::Popularity => "follows",
+                    SortType::Name => "relevance",
+                    SortType::LastUpdated => "updated",
+                    SortType::DateCreated => "newest",
+                },
+            ),
+            ("facets", &format!("[{}]", facets.join(","))),
+        ],
     )
 }
 
-pub fn mod_url(mod_id: u32) -> String {
-    format!("{base}/{mod_id}", base = BASE_URL, mod_id = mod_id,)
+/// Takes either the ID or the slug of a project.
+pub fn modrinth_project_url(project: &str) -> Url {
+    build(MODRINTH_URL, &["project", project], &[])
 }
 
-pub fn info_url(mod_id: u32, file_id: u32) -> String {
-    format!(
-        "{base}/{mod_id}/file/{file_id}",
-        base = BASE_URL,
-        mod_id = mod_id,
-        file_id = file_id,
+pub fn modrinth_versions_url(project: &str, game_version: &str, mod_loader: &ModLoader) -> Url {
+    build(
+        MODRINTH_URL,
+        &["project", project, "version"],
+        &[
+            ("game_versions", &format!("[{}]", json_string(game_version))),
+            ("loaders", modrinth_loaders(mod_loader)),
+        ],
     )
 }
+
+/// Every version of a project, for any game version or mod loader.
+pub fn modrinth_all_versions_url(project: &str) -> Url {
+    build(MODRINTH_URL, &["project", project, "version"], &[])
+}
+
+/// Every category of every project type.
+pub fn modrinth_categories_url() -> Url {
+    build(MODRINTH_URL, &["tag", "category"], &[])
+}
+
+pub fn modrinth_version_url(version: &str) -> Url {
+    build(MODRINTH_URL, &["version", version], &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        let url = search_url("a&b #1+é", "1.16.4", 0, 9, &SortType::Popularity, 0, 6);
+        let query = url.query_pairs().collect::<Vec<_>>();
+        assert!(query.contains(&("searchFilter".into(), "a&b #1+é".into())));
+        assert!(query.contains(&("gameVersion".into(), "1.16.4".into())));
+        assert_eq!(url.fragment(), None);
+
+        assert_eq!(
+            modrinth_project_url("a/b?c").path(),
+            "/v2/project/a%2Fb%3Fc"
+        );
+        assert_eq!(json_string(r#"say "hi"\"#), r#""say \"hi\"\\""#);
+    }
+}
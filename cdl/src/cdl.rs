@@ -1,8 +1,113 @@
-use cdl_lib::models::{ModLoader, SortType};
+use crate::ui;
+use cdl_lib::models::{ModLoader, ProviderKind, ReleaseType, Section, SortType};
+use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::StructOpt;
 
-fn parse_query(src: &str) -> String {
-    src.replace(" ", "%20").into()
+/// A project ID or slug, optionally on a specific provider as
+/// `<provider>:<project>` and pinned to a file as `<project>@<file ID>`.
+/// What IDs look like depends on the provider.
+#[derive(Debug, PartialEq)]
+pub struct ModSpec {
+    pub provider: Option<ProviderKind>,
+    pub project: String,
+    pub file_id: Option<String>,
+}
+
+impl FromStr for ModSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (provider, rest) = match s.split_once(':') {
+            Some((provider, rest)) => (Some(provider.parse()?), rest),
+            None => (None, s),
+        };
+
+        match rest.split_once('@') {
+            Some((_, "")) => Err(format!("'{}' is missing a file ID", s)),
+            Some((project, file_id)) => Ok(Self {
+                provider,
+                project: project.into(),
+                file_id: Some(file_id.into()),
+            }),
+            None => Ok(Self {
+                provider,
+                project: rest.into(),
+                file_id: None,
+            }),
+        }
+    }
+}
+
+/// Indices into the search results, in the same syntax as the prompt.
+#[derive(Debug)]
+pub struct Selection(pub Vec<usize>);
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ui::parse_input(s)
+            .map(Self)
+            .ok_or_else(|| format!("'{}' not a valid selection", s))
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    #[structopt(about = "Updates every mod in the instance to its latest compatible file.")]
+    Update,
+
+    #[structopt(
+        about = "Installs mods with their dependencies without asking anything, or every mod in the instance's manifest if none are given."
+    )]
+    Install {
+        #[structopt(
+            long,
+            help = "Installs exactly the files in the lock file, without resolving anything."
+        )]
+        locked: bool,
+
+        #[structopt(
+            help = "Project IDs or slugs of the mods to install, optionally on another provider as <provider>:<project> and pinned to a file as <project>@<file ID>."
+        )]
+        mods: Vec<ModSpec>,
+    },
+
+    #[structopt(about = "Lists every file of a project, newest first.")]
+    Files {
+        #[structopt(
+            help = "The project ID or slug, optionally on another provider as <provider>:<project>."
+        )]
+        project: ModSpec,
+    },
+
+    #[structopt(about = "Lists the categories that searches can be limited to.")]
+    Categories,
+
+    #[structopt(about = "Removes a mod and offers to remove dependencies no longer needed.")]
+    Remove {
+        #[structopt(help = "The project ID, name or file name of the mod to remove.")]
+        name: String,
+    },
+
+    #[structopt(about = "Checks that every installed file is unchanged since it was installed.")]
+    Verify,
+
+    #[structopt(about = "Manages the download cache shared between instances.")]
+    Cache(CacheCommand),
+}
+
+#[derive(StructOpt, Debug)]
+pub enum CacheCommand {
+    #[structopt(about = "Lists every cached file.")]
+    List,
+
+    #[structopt(about = "Removes every cached file.")]
+    Clean,
+
+    #[structopt(about = "Removes every cached file but the newest of each mod.")]
+    Prune,
 }
 
 #[derive(StructOpt, Debug)]
@@ -14,15 +119,94 @@ pub struct Cdl {
     #[structopt(short = "l", long, possible_values = &["forge", "fabric", "both"], help = "The mod loader to use when searching.")]
     pub mod_loader: Option<ModLoader>,
 
+    #[structopt(long, possible_values = &["curseforge", "modrinth"], help = "Where to search for and install mods from, instead of the instance's provider.")]
+    pub provider: Option<ProviderKind>,
+
     #[structopt(short = "v", long, help = "The version of the game.")]
     pub game_version: Option<String>,
 
+    #[structopt(long, possible_values = &["release", "beta", "alpha"], help = "The least stable kind of file to install when a project has a more stable one.")]
+    pub min_release: Option<ReleaseType>,
+
     #[structopt(short, long, possible_values = &["downloads", "popularity", "name", "updated", "created"], help = "The ordering of search results.")]
     pub sort: Option<SortType>,
 
+    #[structopt(long, possible_values = &["mods", "resourcepacks", "worlds", "modpacks"], help = "The kind of project to search for. Only mods can be installed.")]
+    pub section: Option<Section>,
+
+    #[structopt(
+        long,
+        help = "The name or slug of the category to search in, see `cdl categories`."
+    )]
+    pub category: Option<String>,
+
+    #[structopt(long, help = "Only shows projects by this author.")]
+    pub author: Option<String>,
+
     #[structopt(short, long, help = "The amount of search results to show.")]
     pub amount: Option<u8>,
 
+    #[structopt(
+        long,
+        conflicts_with = "offset",
+        help = "The page of search results to show, starting at 1."
+    )]
+    pub page: Option<usize>,
+
+    #[structopt(long, help = "How many search results to skip.")]
+    pub offset: Option<usize>,
+
+    #[structopt(short, long, help = "The amount of requests to run at the same time.")]
+    pub jobs: Option<usize>,
+
+    #[structopt(
+        short,
+        long,
+        help = "The folder to install mods into, created if it doesn't exist. Defaults to the current folder."
+    )]
+    pub dir: Option<PathBuf>,
+
+    #[structopt(long, help = "Installs optional dependencies without asking.")]
+    pub with_optional: bool,
+
+    #[structopt(
+        long,
+        help = "Installs mods even if they are incompatible with each other."
+    )]
+    pub allow_incompatible: bool,
+
+    #[structopt(
+        long,
+        help = "Prints the mods that would be installed without downloading them."
+    )]
+    pub dry_run: bool,
+
+    #[structopt(
+        long,
+        help = "Selects search results without asking, using the same syntax as the prompt, e.g. \"1-3 5\"."
+    )]
+    pub select: Option<Selection>,
+
+    #[structopt(
+        short,
+        long,
+        help = "Selects the top search result and answers yes to every question."
+    )]
+    pub yes: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "ndjson",
+        help = "Prints search results and download events as a JSON array."
+    )]
+    pub json: bool,
+
+    #[structopt(
+        long,
+        help = "Prints search results and download events as one JSON value per line."
+    )]
+    pub ndjson: bool,
+
     #[structopt(
         short,
         long,
@@ -30,6 +214,47 @@ pub struct Cdl {
     )]
     pub github: bool,
 
-    #[structopt(parse(from_str = parse_query), help = "The query to search for.")]
-    pub query: String,
+    #[structopt(help = "The query to search for.")]
+    pub query: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(provider: Option<ProviderKind>, project: &str, file_id: Option<&str>) -> ModSpec {
+        ModSpec {
+            provider,
+            project: project.into(),
+            file_id: file_id.map(String::from),
+        }
+    }
+
+    #[test]
+    fn mod_specs() {
+        assert_eq!("jei".parse(), Ok(spec(None, "jei", None)));
+        assert_eq!(
+            "jei@3043174".parse(),
+            Ok(spec(None, "jei", Some("3043174")))
+        );
+        assert_eq!(
+            "modrinth:sodium".parse(),
+            Ok(spec(Some(ProviderKind::Modrinth), "sodium", None))
+        );
+        assert_eq!(
+            "curseforge:238222@3043174".parse(),
+            Ok(spec(
+                Some(ProviderKind::CurseForge),
+                "238222",
+                Some("3043174")
+            ))
+        );
+
+        assert!("jei@".parse::<ModSpec>().is_err());
+        assert!("modrinth:sodium@".parse::<ModSpec>().is_err());
+        assert!("github:jei".parse::<ModSpec>().is_err());
+    }
 }
@@ -1,11 +1,14 @@
-use cdl_lib::models::{ModLoader, SortType};
+use cdl_lib::models::{ModLoader, ProviderKind, ReleaseType, SortType};
+use cdl_lib::Retry;
 use serde::{Deserialize, Serialize};
 use std::env;
 use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ConfigError {
     IoError(std::io::Error),
     TomlSerializeError(toml::ser::Error),
@@ -57,11 +60,24 @@ impl From<env::VarError> for ConfigError {
 pub type Result<T> = std::result::Result<T, ConfigError>;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub game_version: String,
     pub mod_loader: ModLoader,
+    pub provider: ProviderKind,
+    /// The least stable kind of file to install, unless a project has
+    /// nothing more stable.
+    pub min_release: ReleaseType,
     pub sort_type: SortType,
     pub amount: u8,
+    pub jobs: usize,
+    /// Where mods are installed when --dir isn't given, instead of the
+    /// current folder.
+    pub mods_dir: Option<PathBuf>,
+    /// How many times a failed download is tried again.
+    pub retries: u32,
+    /// How many seconds to wait for the server before giving up.
+    pub timeout: u64,
 }
 
 impl Config {
@@ -83,6 +99,13 @@ impl Config {
 
         Ok(config)
     }
+
+    pub fn retry(&self) -> Retry {
+        Retry {
+            retries: self.retries,
+            timeout: Duration::from_secs(self.timeout),
+        }
+    }
 }
 
 impl Default for Config {
@@ -90,8 +113,14 @@ impl Default for Config {
         Self {
             game_version: "1.16.4".into(),
             mod_loader: ModLoader::Forge,
+            provider: ProviderKind::CurseForge,
+            min_release: ReleaseType::Release,
             sort_type: SortType::Popularity,
             amount: 9,
+            jobs: 4,
+            mods_dir: None,
+            retries: 3,
+            timeout: 30,
         }
     }
 }
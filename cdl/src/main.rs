@@ -1,13 +1,28 @@
-use cdl_lib::git;
-use std::{error::Error, fs};
-use structopt::StructOpt;
+use cdl_lib::{
+    cache::Cache,
+    git,
+    instance::{FileStatus, Instance, InstanceError},
+    models::{CategoryNode, Filters, ModInfo, ProjectRef, ReleaseType, Section},
+    resolve::{Plan, Resolver},
+};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+use structopt::{
+    clap::{Error as ClapError, ErrorKind},
+    StructOpt,
+};
 
 mod cdl;
 mod config;
+mod output;
 mod ui;
 
-use cdl::Cdl;
+use cdl::{CacheCommand, Cdl, Command, ModSpec};
 use config::Config;
+use output::{Format, Output};
 
 fn choose_branch(repo: &git::Repository) -> git::Result<String> {
     let branches = repo
@@ -25,18 +40,225 @@ fn choose_branch(repo: &git::Repository) -> git::Result<String> {
 
     match input.parse::<usize>() {
         Ok(n) if n > 0 && n <= branches.len() => {
-            return Ok(branches[n - 1].name().unwrap().unwrap().into());
+            Ok(branches[n - 1].name().unwrap().unwrap().into())
         }
 
         _ => {
             println!("There's nothing to do.");
-            return Err(git::GitError::InvalidBranchError);
+            Err(git::GitError::InvalidBranchError)
         }
     }
 }
 
-fn handle_git(cdl: Cdl) -> git::Result<()> {
-    let mut repo = git::clone(&cdl.query)?;
+fn print_plan_warnings(plan: &Plan, version: &str, min_release: ReleaseType, out: &Output) {
+    let name = |project: ProjectRef| {
+        plan.mods
+            .iter()
+            .find(|m| m.info.project() == project)
+            .map(|m| m.info.display_name.clone())
+            .unwrap_or_else(|| project.to_string())
+    };
+
+    for cycle in &plan.cycles {
+        let names = cycle.iter().map(|&id| name(id)).collect::<Vec<_>>();
+        out.message(format!(
+            "Warning: circular dependency between {}.",
+            names.join(" -> ")
+        ));
+    }
+
+    for project in &plan.missing {
+        out.message(format!(
+            "Warning: project {} has no file for {}.",
+            project, version
+        ));
+    }
+
+    for &project in &plan.unstable {
+        if let Some(m) = plan.mods.iter().find(|m| m.info.project() == project) {
+            out.message(format!(
+                "Warning: {} has no {} file for {}, using the {} file {}.",
+                m.info.display_name, min_release, version, m.info.release_type, m.info.file_name
+            ));
+        }
+    }
+}
+
+/// Prints every explicitly requested mod in the plan along with the tree of
+/// its dependencies, without downloading anything.
+fn print_plan(plan: &Plan, instance: &Instance, out: &Output) {
+    fn print_mod(
+        plan: &Plan,
+        instance: &Instance,
+        info: &ModInfo,
+        path: &mut Vec<ProjectRef>,
+        out: &Output,
+    ) {
+        let exists = instance.dir.join(&info.file_name).exists();
+        out.message(format!(
+            "{}{} ({}{}{})",
+            match path.len() {
+                0 => "<== ".to_string(),
+                depth => "    ".repeat(depth),
+            },
+            info.file_name,
+            ui::format_size(info.file_length),
+            if info.provider != instance.manifest.provider {
+                format!(", from {}", info.provider)
+            } else {
+                String::new()
+            },
+            if exists { ", already exists" } else { "" },
+        ));
+
+        path.push(info.project());
+        for dep in info.hard_dependencies() {
+            if path.contains(&dep.project()) {
+                continue;
+            }
+
+            if let Some(m) = plan.mods.iter().find(|m| m.info.project() == dep.project()) {
+                print_mod(plan, instance, &m.info, path, out);
+            }
+        }
+        path.pop();
+    }
+
+    for planned in plan.mods.iter().filter(|m| m.explicit) {
+        print_mod(plan, instance, &planned.info, &mut vec![], out);
+    }
+
+    let (existing, new): (Vec<_>, Vec<_>) = plan
+        .mods
+        .iter()
+        .partition(|m| instance.dir.join(&m.info.file_name).exists());
+    out.message(format!(
+        "{} files would be downloaded ({}), {} already exist.",
+        new.len(),
+        ui::format_size(new.iter().map(|m| m.info.file_length).sum()),
+        existing.len(),
+    ));
+}
+
+/// Reports every incompatibility in the plan, returning whether installing
+/// should go ahead.
+fn check_conflicts(
+    plan: &Plan,
+    instance: &Instance,
+    allow_incompatible: bool,
+    out: &Output,
+) -> bool {
+    let conflicts = plan.conflicts(instance);
+    if conflicts.is_empty() {
+        return true;
+    }
+
+    out.message("The following mods are incompatible:");
+    for c in &conflicts {
+        out.message(format!(
+            "    {} ({}) is incompatible with {} ({})",
+            c.name, c.project, c.other_name, c.other
+        ));
+    }
+
+    if allow_incompatible {
+        out.message("Installing anyway.");
+    } else {
+        out.message("Refusing to install, pass --allow-incompatible to install anyway.");
+    }
+    allow_incompatible
+}
+
+/// Offers the optional dependencies of the planned mods, or takes all of
+/// them if `with_optional` is set, until there are no new ones to offer.
+async fn choose_optional(
+    resolver: &mut Resolver<'_>,
+    with_optional: bool,
+) -> Result<Plan, cdl_lib::DownloadError> {
+    let mut declined = vec![];
+    loop {
+        let plan = resolver.plan();
+        let optional = plan
+            .optional
+            .iter()
+            .filter(|(id, _)| !declined.contains(id))
+            .collect::<Vec<_>>();
+
+        if optional.is_empty() {
+            return Ok(plan);
+        }
+
+        let chosen = if with_optional {
+            optional.iter().map(|(id, _)| *id).collect::<Vec<_>>()
+        } else {
+            let mut rows = vec![];
+            for &&(project, wanted_by) in &optional {
+                let name = project.provider.provider().get_mod(project.id).await?.name;
+                let wanted_by = plan
+                    .mods
+                    .iter()
+                    .find(|m| m.info.project() == wanted_by)
+                    .map(|m| m.info.display_name.clone())
+                    .unwrap_or_default();
+                rows.push((name, wanted_by));
+            }
+
+            println!("The following optional dependencies are available:");
+            ui::print_indexed_list2(
+                &["NAME", "WANTED BY"],
+                &rows,
+                |r| r.0.clone(),
+                |r| r.1.clone(),
+            );
+
+            let input = ui::parse_input(&ui::read_input()?).unwrap_or_default();
+            optional
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| input.contains(&(i + 1)))
+                .map(|(_, (id, _))| *id)
+                .collect()
+        };
+
+        declined.extend(
+            optional
+                .iter()
+                .map(|(id, _)| *id)
+                .filter(|id| !chosen.contains(id)),
+        );
+
+        if chosen.is_empty() {
+            return Ok(plan);
+        }
+
+        resolver.add(&chosen).await?;
+    }
+}
+
+/// Asks about optional dependencies when `interactive`, and otherwise only
+/// takes them when --with-optional is passed.
+async fn resolve_optional(
+    resolver: &mut Resolver<'_>,
+    cdl: &Cdl,
+    interactive: bool,
+    out: &Output,
+) -> Result<Plan, cdl_lib::DownloadError> {
+    if interactive || cdl.with_optional {
+        return choose_optional(resolver, cdl.with_optional).await;
+    }
+
+    let plan = resolver.plan();
+    if !plan.optional.is_empty() {
+        out.message(format!(
+            "Skipping {} optional dependencies, pass --with-optional to install them.",
+            plan.optional.len()
+        ));
+    }
+    Ok(plan)
+}
+
+fn handle_git(cdl: Cdl, dir: &Path) -> git::Result<()> {
+    let mut repo = git::clone(cdl.query.as_deref().unwrap_or_default())?;
 
     println!("The following branches were found, please select one:");
     let branch = choose_branch(&repo)?;
@@ -55,9 +277,10 @@ fn handle_git(cdl: Cdl) -> git::Result<()> {
 
     let input = ui::read_input()?;
     if let Some(input) = ui::parse_input(&input) {
+        fs::create_dir_all(dir)?;
         for n in input {
             if n > 0 && n <= jars.len() {
-                fs::copy(&mut jars[n - 1].path(), &mut jars[n - 1].file_name())?;
+                fs::copy(jars[n - 1].path(), dir.join(jars[n - 1].file_name()))?;
             } else {
                 return Err(git::GitError::InvalidBranchError);
             }
@@ -67,72 +290,703 @@ fn handle_git(cdl: Cdl) -> git::Result<()> {
     Ok(())
 }
 
-async fn handle_search(cdl: Cdl, config: Config) -> Result<(), cdl_lib::DownloadError> {
-    let version = cdl.game_version.as_ref().unwrap_or(&config.game_version);
-    let loader = cdl.mod_loader.as_ref().unwrap_or(&config.mod_loader);
+/// Opens the instance in `dir`, or a new one for the game version, mod
+/// loader and provider given on the command line or in the config. Returns
+/// `None` if the existing instance targets another game version.
+fn open_instance(
+    cdl: &Cdl,
+    config: &Config,
+    dir: &Path,
+    out: &Output,
+) -> Result<Option<Instance>, InstanceError> {
+    let existing = Instance::load(dir)?;
+    let version = cdl
+        .game_version
+        .clone()
+        .or_else(|| existing.as_ref().map(|i| i.manifest.game_version.clone()))
+        .unwrap_or_else(|| config.game_version.clone());
+    let loader = cdl
+        .mod_loader
+        .clone()
+        .or_else(|| existing.as_ref().map(|i| i.manifest.mod_loader.clone()))
+        .unwrap_or_else(|| config.mod_loader.clone());
+    let provider = cdl.provider.unwrap_or(config.provider);
+
+    match existing {
+        Some(instance) if instance.manifest.game_version != version => {
+            out.message(format!(
+                "This instance is for {}, refusing to install mods for {}.",
+                instance.manifest.game_version, version,
+            ));
+            Ok(None)
+        }
+        Some(instance) => Ok(Some(instance)),
+        None => Ok(Some(Instance::new(dir, &version, &loader, provider))),
+    }
+}
+
+/// Where a search is among its pages of results.
+struct Pages {
+    index: usize,
+    number: usize,
+    page_size: usize,
+    /// Where each page before the current one started, which can't be told
+    /// from the page size when the provider skipped filtered out results.
+    starts: Vec<usize>,
+    paged_forward: bool,
+}
+
+impl Pages {
+    fn new(index: usize, page_size: usize) -> Self {
+        Self {
+            index,
+            number: index / page_size.max(1) + 1,
+            page_size,
+            starts: vec![],
+            paged_forward: false,
+        }
+    }
+
+    fn next(&mut self, next: Option<usize>) {
+        self.starts.push(self.index);
+        self.index = next.unwrap_or(self.index + self.page_size);
+        self.number += 1;
+        self.paged_forward = true;
+    }
+
+    fn previous(&mut self) {
+        self.index = self
+            .starts
+            .pop()
+            .unwrap_or_else(|| self.index.saturating_sub(self.page_size));
+        self.number = self.number.saturating_sub(1).max(1);
+        self.paged_forward = false;
+    }
+}
+
+/// What was entered below a page of search results.
+enum PageInput {
+    Next,
+    Previous,
+    Other(String),
+}
+
+/// Asks for `choice`, or to go to the next or previous page when there is
+/// one.
+fn prompt_page(choice: &str, has_more: bool, has_previous: bool) -> std::io::Result<PageInput> {
+    let mut hints = vec![];
+    if has_more {
+        hints.push("n for the next page");
+    }
+    if has_previous {
+        hints.push("p for the previous page");
+    }
+    if !hints.is_empty() {
+        println!("Enter {}, or {}.", choice, hints.join(" or "));
+    }
+
+    Ok(match ui::read_input()? {
+        input if input == "n" && has_more => PageInput::Next,
+        input if input == "p" && has_previous => PageInput::Previous,
+        input => PageInput::Other(input),
+    })
+}
+
+async fn handle_search(
+    cdl: Cdl,
+    config: Config,
+    dir: &Path,
+    out: &Output,
+) -> Result<(), cdl_lib::DownloadError> {
+    let query = cdl.query.as_deref().unwrap_or_default();
+    let mut instance = match open_instance(&cdl, &config, dir, out)? {
+        Some(instance) => instance,
+        None => return Ok(()),
+    };
+    let version = instance.manifest.game_version.clone();
+    let loader = instance.manifest.mod_loader.clone();
     let amount = cdl.amount.unwrap_or(config.amount);
     let sort_type = cdl.sort.as_ref().unwrap_or(&config.sort_type);
 
-    let search_results =
-        cdl_lib::get_search_results(&cdl.query, version, amount, sort_type, loader).await?;
+    let provider = cdl.provider.unwrap_or(instance.manifest.provider);
+    let section = cdl.section.unwrap_or(Section::Mods);
+    let category = match &cdl.category {
+        Some(name) => {
+            let categories = provider.provider().get_categories(section).await?;
+            match CategoryNode::find(&categories, name) {
+                Some(category) => Some(category.clone()),
+                None => {
+                    out.message(format!(
+                        "No category '{}' for {} on {}, see `cdl categories`.",
+                        name, section, provider
+                    ));
+                    return Ok(());
+                }
+            }
+        }
+        None => None,
+    };
+    let filters = Filters {
+        category,
+        section,
+        author: cdl.author.clone(),
+        ..Filters::mods(&loader)
+    };
+    // Other kinds of projects don't belong in the mods folder.
+    let installable = section == Section::Mods;
+    let what = match section {
+        Section::Mods => format!("{} mods", loader),
+        section => section.to_string(),
+    };
 
-    if search_results.len() == 0 {
-        println!(
-            "No {} mods for {} including '{}' found.",
-            loader.to_string(),
-            version,
-            cdl.query,
-        );
-        return Ok(());
-    }
+    let interactive = cdl.select.is_none() && !cdl.yes;
+    let page_size = amount as usize;
+    let mut pages = Pages::new(cdl.offset.unwrap_or_default(), page_size);
+    // Pages are walked to rather than computed, since the provider may have
+    // skipped filtered out results.
+    let mut skip = cdl.page.map_or(0, |page| page.saturating_sub(1));
 
-    ui::print_indexed_list2(
-        &["NAME", "AUTHOR"],
-        &search_results,
-        |r| r.name.clone(),
-        |r| r.author_names(),
-    );
+    let (search_results, input) = loop {
+        let page = provider
+            .provider()
+            .search(query, &version, &filters, pages.index, amount, sort_type)
+            .await?;
 
-    println!(
-        "Searched {} mods for {} including '{}'.",
-        loader.to_string(),
-        version,
-        cdl.query,
-    );
+        if skip > 0 && page.has_more() {
+            skip -= 1;
+            pages.next(page.next);
+            continue;
+        }
 
-    let input = ui::read_input()?;
+        // A page can come back empty but with more after it when the
+        // provider gave up filling it.
+        if page.items.is_empty() && !page.has_more() {
+            // The provider couldn't tell that the last page was the last.
+            if pages.paged_forward {
+                out.message("There are no more results.");
+                skip = 0;
+                pages.previous();
+                continue;
+            }
+
+            out.message(format!(
+                "No {} for {} including '{}' found.",
+                what, version, query,
+            ));
+            return Ok(());
+        }
+
+        if skip > 0 {
+            out.message(format!("There are only {} pages of results.", pages.number));
+            skip = 0;
+        }
+
+        if !out.is_human() && interactive {
+            out.search_results(&page.items);
+            return Ok(());
+        }
+
+        if out.is_human() {
+            ui::print_indexed_list2(
+                &["NAME", "AUTHOR"],
+                &page.items,
+                |r| r.name.clone(),
+                |r| r.author_names(),
+            );
+        }
+
+        out.message(format!(
+            "Searched {} for {} including '{}', page {}{}.",
+            what,
+            version,
+            query,
+            pages.number,
+            match page.total {
+                Some(total) => format!(" of {}", total.div_ceil(page_size.max(1))),
+                None => String::new(),
+            },
+        ));
+
+        let has_previous = pages.index > 0;
+        let entered = match &cdl.select {
+            _ if !installable => {
+                if !interactive || (!page.has_more() && !has_previous) {
+                    out.message("Only mods can be installed.");
+                    return Ok(());
+                }
+                prompt_page("nothing to quit", page.has_more(), has_previous)?
+            }
+            Some(select) => break (page.items, Some(select.0.clone())),
+            None if cdl.yes => break (page.items, Some(vec![1])),
+            None => prompt_page("the mods to install", page.has_more(), has_previous)?,
+        };
 
-    let input = match ui::parse_input(&input) {
+        match entered {
+            PageInput::Next => pages.next(page.next),
+            PageInput::Previous => pages.previous(),
+            PageInput::Other(_) if !installable => return Ok(()),
+            PageInput::Other(input) => break (page.items, ui::parse_input(&input)),
+        }
+    };
+
+    let input = match input {
         Some(input) if input.iter().all(|i| *i <= search_results.len()) => input,
         _ => {
-            println!("There's nothing to do.");
+            out.message("There's nothing to do.");
             return Ok(());
         }
     };
 
-    let mods = search_results
+    let ids = search_results
         .iter()
         .enumerate()
         .filter(|(i, _)| input.contains(&(i + 1)))
-        .map(|(_, r)| r)
+        .map(|(_, r)| ProjectRef::new(provider, r.id))
         .collect::<Vec<_>>();
 
-    cdl_lib::download_all(version, &mods[..], |event| {
-        use cdl_lib::EventType::*;
-        match event {
-            MainDownloading(info) => print!("<== Downloading {}... ", info.file_name),
-            MainDownloaded(_) => println!("done!"),
-            MainAlreadyDownloaded(info) => {
-                println!("<== {} is already downloaded.", info.file_name)
+    let jobs = cdl.jobs.unwrap_or(config.jobs);
+    let min_release = cdl.min_release.unwrap_or(config.min_release);
+    let mut resolver = Resolver::new(
+        instance.manifest.provider,
+        &version,
+        &loader,
+        min_release,
+        jobs,
+    );
+    resolver.pin_installed(&instance);
+    resolver.add(&ids).await?;
+    let plan = resolve_optional(&mut resolver, &cdl, interactive, out).await?;
+    print_plan_warnings(&plan, &version, min_release, out);
+
+    if !check_conflicts(&plan, &instance, cdl.allow_incompatible, out) {
+        return Ok(());
+    }
+
+    if cdl.dry_run {
+        print_plan(&plan, &instance, out);
+        return Ok(());
+    }
+
+    cdl_lib::download_all(
+        &mut instance,
+        &plan,
+        jobs,
+        Cache::open().as_ref(),
+        &config.retry(),
+        |e| out.event(e),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn handle_update(
+    dir: &Path,
+    config: &Config,
+    min_release: ReleaseType,
+    jobs: usize,
+    yes: bool,
+    out: &Output,
+) -> Result<(), cdl_lib::DownloadError> {
+    let mut instance = match Instance::load(dir)? {
+        Some(instance) => instance,
+        None => {
+            out.message(format!("There's no instance in '{}'.", dir.display()));
+            return Ok(());
+        }
+    };
+
+    let updates = cdl_lib::find_updates(&instance, min_release, jobs).await?;
+
+    if updates.is_empty() {
+        out.message("Everything is up to date.");
+        return Ok(());
+    }
+
+    out.message("The following mods will be updated:");
+    for update in &updates {
+        let new_dependencies = update.new_dependencies(&instance).len();
+        out.message(format!(
+            "    {} -> {}{}",
+            update.old.file_name,
+            update.new.file_name,
+            match new_dependencies {
+                0 => String::new(),
+                n => format!(" (+{} new dependencies)", n),
+            },
+        ));
+    }
+
+    for update in updates.iter().filter(|u| u.new.release_type < min_release) {
+        out.message(format!(
+            "Warning: {} has no {} file, updating to the {} file {}.",
+            update.new.display_name, min_release, update.new.release_type, update.new.file_name
+        ));
+    }
+
+    if !yes && !ui::confirm("Proceed?")? {
+        out.message("There's nothing to do.");
+        return Ok(());
+    }
+
+    cdl_lib::update_all(
+        &mut instance,
+        &updates,
+        min_release,
+        jobs,
+        Cache::open().as_ref(),
+        &config.retry(),
+        |e| out.event(e),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn handle_install(
+    cdl: &Cdl,
+    config: &Config,
+    locked: bool,
+    mods: &[ModSpec],
+    dir: &Path,
+    jobs: usize,
+    out: &Output,
+) -> Result<bool, cdl_lib::DownloadError> {
+    let existing = if mods.is_empty() {
+        Instance::load(dir)?
+    } else {
+        open_instance(cdl, config, dir, out)?
+    };
+
+    let mut instance = match existing {
+        Some(instance) => instance,
+        None if mods.is_empty() => {
+            out.message(format!("There's no instance in '{}'.", dir.display()));
+            return Ok(false);
+        }
+        None => return Ok(false),
+    };
+
+    if locked {
+        cdl_lib::install_locked(
+            &instance,
+            jobs,
+            Cache::open().as_ref(),
+            &config.retry(),
+            |e| out.event(e),
+        )
+        .await?;
+        return Ok(true);
+    }
+
+    let game_version = instance.manifest.game_version.clone();
+    let mod_loader = instance.manifest.mod_loader.clone();
+    let min_release = cdl.min_release.unwrap_or(config.min_release);
+    let mut resolver = Resolver::new(
+        instance.manifest.provider,
+        &game_version,
+        &mod_loader,
+        min_release,
+        jobs,
+    );
+    resolver.pin_installed(&instance);
+
+    let mut ids = vec![];
+    for spec in mods {
+        let kind = spec
+            .provider
+            .or(cdl.provider)
+            .unwrap_or(instance.manifest.provider);
+        let provider = kind.provider();
+        let id = match provider.find_mod(&spec.project, &game_version).await? {
+            Some(result) => ProjectRef::new(kind, result.id),
+            None => {
+                out.message(format!(
+                    "No mod with the slug '{}' found on {}.",
+                    spec.project, kind
+                ));
+                return Ok(false);
+            }
+        };
+
+        if let Some(file_id) = &spec.file_id {
+            match provider.parse_id(file_id) {
+                Some(file_id) => resolver.pin(id, file_id),
+                None => {
+                    out.message(format!("'{}' is not a valid file ID.", file_id));
+                    return Ok(false);
+                }
             }
-            MainError(_, _) => println!("errored."),
-            DepDownloading(info) => print!("    Downloading {}... ", info.file_name),
-            DepDownloaded(_) => println!("done!"),
-            DepAlreadyDownloaded(info) => println!("    {} is already downloaded.", info.file_name),
-            DepError(_, _) => println!("errored."),
         }
-    })
+        ids.push(id);
+    }
+
+    if ids.is_empty() {
+        ids = instance.manifest.mods.clone();
+    }
+
+    resolver.add(&ids).await?;
+    let plan = resolve_optional(&mut resolver, cdl, false, out).await?;
+    print_plan_warnings(&plan, &game_version, min_release, out);
+
+    if !check_conflicts(&plan, &instance, cdl.allow_incompatible, out) {
+        return Ok(false);
+    }
+
+    if cdl.dry_run {
+        print_plan(&plan, &instance, out);
+        return Ok(true);
+    }
+
+    cdl_lib::download_all(
+        &mut instance,
+        &plan,
+        jobs,
+        Cache::open().as_ref(),
+        &config.retry(),
+        |e| out.event(e),
+    )
     .await?;
+    Ok(true)
+}
+
+async fn handle_categories(
+    cdl: &Cdl,
+    config: &Config,
+    dir: &Path,
+    out: &Output,
+) -> Result<(), cdl_lib::DownloadError> {
+    let kind = match cdl.provider {
+        Some(kind) => kind,
+        None => Instance::load(dir)?.map_or(config.provider, |i| i.manifest.provider),
+    };
+    let section = cdl.section.unwrap_or(Section::Mods);
+    let categories = kind.provider().get_categories(section).await?;
+
+    if !out.is_human() {
+        out.categories(&categories);
+        return Ok(());
+    }
+
+    fn print_tree(categories: &[CategoryNode], depth: usize) {
+        for category in categories {
+            println!(
+                "{:indent$}{} ({})",
+                "",
+                category.name,
+                category.slug,
+                indent = depth * 2
+            );
+            print_tree(&category.children, depth + 1);
+        }
+    }
+
+    print_tree(&categories, 0);
+    out.message(format!(
+        "Listed the categories for {} on {}. Search one with `cdl --category <name> <query>`.",
+        section, kind
+    ));
+
+    Ok(())
+}
+
+async fn handle_files(
+    cdl: &Cdl,
+    config: &Config,
+    spec: &ModSpec,
+    dir: &Path,
+    out: &Output,
+) -> Result<(), cdl_lib::DownloadError> {
+    let instance = Instance::load(dir)?;
+    let kind = spec
+        .provider
+        .or(cdl.provider)
+        .or_else(|| instance.as_ref().map(|i| i.manifest.provider))
+        .unwrap_or(config.provider);
+    let game_version = cdl
+        .game_version
+        .clone()
+        .or_else(|| instance.as_ref().map(|i| i.manifest.game_version.clone()))
+        .unwrap_or_else(|| config.game_version.clone());
+
+    if spec.file_id.is_some() {
+        out.message("Listing files takes a project without a file ID.");
+        return Ok(());
+    }
+
+    let provider = kind.provider();
+    let project = match provider.find_mod(&spec.project, &game_version).await? {
+        Some(project) => project,
+        None => {
+            out.message(format!(
+                "No mod with the slug '{}' found on {}.",
+                spec.project, kind
+            ));
+            return Ok(());
+        }
+    };
+
+    let files = provider.get_files(project.id).await?;
+
+    if !out.is_human() {
+        out.files(&files);
+        return Ok(());
+    }
+
+    println!("        ID  DATE        TYPE     FILE");
+    for file in &files {
+        println!(
+            "{:>10}  {:<10}  {:<7}  {}",
+            provider.format_id(file.id),
+            file.date.get(..10).unwrap_or(&file.date),
+            file.release_type,
+            file.file_name,
+        );
+        println!("{:>33}{}", "", file.game_versions.join(", "));
+    }
+    println!(
+        "{} has {} files. Install one with `cdl install {}@<ID>`.",
+        project.name,
+        files.len(),
+        spec.project,
+    );
+
+    Ok(())
+}
+
+fn handle_remove(dir: &Path, name: &str, yes: bool) -> Result<(), cdl_lib::DownloadError> {
+    let mut instance = match Instance::load(dir)? {
+        Some(instance) => instance,
+        None => {
+            println!("There's no instance in '{}'.", dir.display());
+            return Ok(());
+        }
+    };
+
+    let project = match instance.find(name) {
+        Some(m) => m.project(),
+        None => {
+            println!("No installed mod matches '{}'.", name);
+            return Ok(());
+        }
+    };
+
+    if let Some(removed) = instance.remove(project)? {
+        println!("<== Removed {}.", removed.file_name);
+    }
+    instance.save()?;
+
+    let orphans = instance
+        .orphans()
+        .into_iter()
+        .map(|m| m.project())
+        .collect::<Vec<_>>();
+
+    if orphans.is_empty() {
+        return Ok(());
+    }
+
+    println!("The following dependencies are no longer required by anything:");
+    for &project in &orphans {
+        if let Some(m) = instance.get(project) {
+            println!("    {}", m.file_name);
+        }
+    }
+
+    if !yes && !ui::confirm("Remove them?")? {
+        return Ok(());
+    }
+
+    for project in orphans {
+        if let Some(removed) = instance.remove(project)? {
+            println!("    Removed {}.", removed.file_name);
+        }
+    }
+    instance.save()?;
+
+    Ok(())
+}
+
+/// Returns whether every installed file is intact.
+fn handle_verify(dir: &Path) -> Result<bool, InstanceError> {
+    let instance = match Instance::load(dir)? {
+        Some(instance) => instance,
+        None => {
+            println!("There's no instance in '{}'.", dir.display());
+            return Ok(true);
+        }
+    };
+
+    let mut broken = 0;
+    for (locked, status) in instance.verify()? {
+        match status {
+            FileStatus::Ok => continue,
+            FileStatus::Missing => println!("    {} is missing.", locked.file_name),
+            FileStatus::Modified { actual } => println!(
+                "    {} has been modified, expected {}, got {}.",
+                locked.file_name, locked.sha256, actual
+            ),
+        }
+        broken += 1;
+    }
+
+    if broken == 0 {
+        println!("<== All {} files are intact.", instance.lock.mods.len());
+        return Ok(true);
+    }
+
+    println!(
+        "<== {} of {} files are broken, run `cdl install --locked` to download them again.",
+        broken,
+        instance.lock.mods.len()
+    );
+    Ok(false)
+}
+
+fn handle_cache(command: &CacheCommand) -> std::io::Result<()> {
+    let cache = match Cache::open() {
+        Some(cache) => cache,
+        None => {
+            println!("Couldn't find the cache, neither XDG_CACHE_HOME nor HOME is set.");
+            return Ok(());
+        }
+    };
+
+    match command {
+        CacheCommand::List => {
+            let files = cache.list()?;
+            if files.is_empty() {
+                println!("The cache in {} is empty.", cache.dir.display());
+                return Ok(());
+            }
+
+            let total = files.iter().map(|f| f.size).sum();
+            for file in &files {
+                println!(
+                    "{:>8} {:>8}  {:>9}  {}",
+                    file.project_id,
+                    file.file_id,
+                    ui::format_size(file.size),
+                    file.file_name,
+                );
+            }
+            println!(
+                "{} files, {} in {}.",
+                files.len(),
+                ui::format_size(total),
+                cache.dir.display()
+            );
+        }
+        CacheCommand::Clean => {
+            println!("<== Removed {} cached files.", cache.clean()?);
+        }
+        CacheCommand::Prune => {
+            let removed = cache.prune()?;
+            for file in &removed {
+                println!("    Removed {}.", file.file_name);
+            }
+            println!("<== Removed {} cached files.", removed.len());
+        }
+    }
+
     Ok(())
 }
 
@@ -141,11 +995,50 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let cdl = Cdl::from_args();
     let config = config::Config::load()?;
 
-    if cdl.github {
-        handle_git(cdl)?;
+    let jobs = cdl.jobs.unwrap_or(config.jobs);
+    let dir = cdl
+        .dir
+        .clone()
+        .or_else(|| config.mods_dir.clone())
+        .unwrap_or_else(|| PathBuf::from("."));
+    let out = Output::new(if cdl.ndjson {
+        Format::NdJson
+    } else if cdl.json {
+        Format::Json
     } else {
-        handle_search(cdl, config).await?;
+        Format::Human
+    });
+
+    match cdl.command {
+        Some(Command::Update) => {
+            let min_release = cdl.min_release.unwrap_or(config.min_release);
+            handle_update(&dir, &config, min_release, jobs, cdl.yes, &out).await?
+        }
+        Some(Command::Install { locked, ref mods }) => {
+            if !handle_install(&cdl, &config, locked, mods, &dir, jobs, &out).await? {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Files { ref project }) => {
+            handle_files(&cdl, &config, project, &dir, &out).await?
+        }
+        Some(Command::Categories) => handle_categories(&cdl, &config, &dir, &out).await?,
+        Some(Command::Remove { ref name }) => handle_remove(&dir, name, cdl.yes)?,
+        Some(Command::Verify) => {
+            if !handle_verify(&dir)? {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Cache(ref command)) => handle_cache(command)?,
+        None if cdl.query.is_none() => ClapError::with_description(
+            "The following required arguments were not provided:\n    <query>",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+        None if cdl.github => handle_git(cdl, &dir)?,
+        None => handle_search(cdl, config, &dir, &out).await?,
     }
 
+    out.finish();
     Ok(())
 }
@@ -0,0 +1,284 @@
+use crate::ui;
+use cdl_lib::{
+    models::{CategoryNode, ProjectFile, SearchResult},
+    EventType,
+};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// How often progress is redrawn or reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Human,
+    /// A single JSON array, printed once the command has succeeded.
+    Json,
+    /// One JSON value per line, printed as soon as it is available.
+    NdJson,
+}
+
+pub struct Output {
+    format: Format,
+    buffer: RefCell<Vec<Value>>,
+    progress: RefCell<Progress>,
+    /// Whether to draw a progress line, which only makes sense on a terminal.
+    draw_progress: bool,
+}
+
+/// Every file that has started downloading, for drawing the progress line.
+#[derive(Default)]
+struct Progress {
+    /// Bytes received and total size, if known, by file ID.
+    files: HashMap<u64, (u64, Option<u64>)>,
+    /// The ID and name of the file that most recently made progress.
+    current: Option<(u64, String)>,
+    last_update: Option<Instant>,
+    drawn: bool,
+}
+
+impl Progress {
+    /// Whether enough time has passed since the last update.
+    fn due(&mut self) -> bool {
+        let due = self
+            .last_update
+            .is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL);
+        if due {
+            self.last_update = Some(Instant::now());
+        }
+        due
+    }
+
+    /// Counts a file as completely downloaded, whatever its progress was.
+    fn finish(&mut self, file_id: u64) {
+        if let Some((received, total)) = self.files.get_mut(&file_id) {
+            let size = total.unwrap_or(*received).max(*received);
+            *received = size;
+            *total = Some(size);
+        }
+    }
+
+    /// Draws the progress of the current file and of every file together
+    /// on a single line of stderr, replacing whatever was drawn before.
+    fn draw(&mut self) {
+        let (id, name) = match &self.current {
+            Some(current) => current,
+            None => return,
+        };
+        let (received, total) = self.files.get(id).copied().unwrap_or_default();
+        let file = match total {
+            Some(total) => format!(
+                "{} {}",
+                ui::progress_bar(received, total, 20),
+                ui::format_size(total)
+            ),
+            None => ui::format_size(received),
+        };
+
+        let all_received = self.files.values().map(|(r, _)| r).sum();
+        let all_total = self.files.values().map(|(r, t)| t.unwrap_or(*r)).sum();
+        let done = self.files.values().filter(|(r, t)| Some(*r) == *t).count();
+
+        eprint!(
+            "\r\x1b[K    {} {}  {} {} of {}, {}/{} files",
+            name,
+            file,
+            ui::progress_bar(all_received, all_total, 20),
+            ui::format_size(all_received),
+            ui::format_size(all_total),
+            done,
+            self.files.len(),
+        );
+        let _ = io::stderr().flush();
+        self.drawn = true;
+    }
+
+    fn clear(&mut self) {
+        if self.drawn {
+            eprint!("\r\x1b[K");
+            let _ = io::stderr().flush();
+            self.drawn = false;
+        }
+    }
+}
+
+impl Output {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            buffer: RefCell::new(vec![]),
+            progress: RefCell::new(Progress::default()),
+            draw_progress: format == Format::Human && io::stderr().is_terminal(),
+        }
+    }
+
+    pub fn is_human(&self) -> bool {
+        self.format == Format::Human
+    }
+
+    /// Prints a message meant for people, which goes to stderr instead when
+    /// the output is machine-readable.
+    pub fn message<D: Display>(&self, message: D) {
+        match self.format {
+            Format::Human => println!("{}", message),
+            _ => eprintln!("{}", message),
+        }
+    }
+
+    pub fn search_results(&self, results: &[SearchResult]) {
+        for result in results {
+            self.emit(serde_json::to_value(result).unwrap_or(Value::Null));
+        }
+    }
+
+    pub fn categories(&self, categories: &[CategoryNode]) {
+        for category in categories {
+            self.emit(serde_json::to_value(category).unwrap_or(Value::Null));
+        }
+    }
+
+    pub fn files(&self, files: &[ProjectFile]) {
+        for file in files {
+            self.emit(serde_json::to_value(file).unwrap_or(Value::Null));
+        }
+    }
+
+    /// Reports a download event. Progress is drawn as a line on stderr that
+    /// is replaced as it changes, reported at most every
+    /// `PROGRESS_INTERVAL` as NDJSON, and left out of JSON arrays entirely.
+    pub fn event(&self, event: EventType) {
+        use EventType::*;
+        if self.is_human() {
+            let mut progress = self.progress.borrow_mut();
+            match event {
+                MainDownloading(info) | DepDownloading(info) => {
+                    let total = Some(info.file_length).filter(|&l| l != 0);
+                    progress.files.insert(info.id, (0, total));
+                    return;
+                }
+                MainProgress(info, received, total) | DepProgress(info, received, total) => {
+                    progress.files.insert(info.id, (received, total));
+                    progress.current = Some((info.id, info.file_name.clone()));
+                    if self.draw_progress && progress.due() {
+                        progress.draw();
+                    }
+                    return;
+                }
+                MainDownloaded(info)
+                | MainCached(info)
+                | MainError(info, _)
+                | DepDownloaded(info)
+                | DepCached(info)
+                | DepError(info, _) => progress.finish(info.id),
+                _ => {}
+            }
+
+            progress.clear();
+            match event {
+                MainDownloading(_) | DepDownloading(_) | MainProgress(..) | DepProgress(..) => {}
+                MainDownloaded(info) => println!("<== Downloaded {}.", info.file_name),
+                MainCached(info) => println!("<== Copied {} from the cache.", info.file_name),
+                MainRetrying(info, attempt, e) | DepRetrying(info, attempt, e) => println!(
+                    "    Retrying {} (attempt {}) after error: {}",
+                    info.file_name, attempt, e
+                ),
+                MainAlreadyDownloaded(info) => {
+                    println!("<== {} is already downloaded.", info.file_name)
+                }
+                MainError(info, e) => {
+                    println!("<== Failed to download {}: {}", info.file_name, e)
+                }
+                DepDownloaded(info) => println!("    Downloaded {}.", info.file_name),
+                DepCached(info) => println!("    Copied {} from the cache.", info.file_name),
+                DepAlreadyDownloaded(info) => {
+                    println!("    {} is already downloaded.", info.file_name)
+                }
+                DepError(info, e) => println!("    Failed to download {}: {}", info.file_name, e),
+            }
+
+            if self.draw_progress && progress.files.values().any(|(r, t)| Some(*r) != *t) {
+                progress.draw();
+            }
+            return;
+        }
+
+        if let MainProgress(_, received, total) | DepProgress(_, received, total) = event {
+            let finished = Some(received) == total;
+            if self.format == Format::Json || !(finished || self.progress.borrow_mut().due()) {
+                return;
+            }
+        }
+
+        let attempt = match event {
+            MainRetrying(_, attempt, _) | DepRetrying(_, attempt, _) => Some(attempt),
+            _ => None,
+        };
+        let progress = match event {
+            MainProgress(_, received, total) | DepProgress(_, received, total) => {
+                Some((received, total))
+            }
+            _ => None,
+        };
+
+        let (name, info, error) = match event {
+            MainAlreadyDownloaded(info) => ("MainAlreadyDownloaded", info, None),
+            MainDownloading(info) => ("MainDownloading", info, None),
+            MainDownloaded(info) => ("MainDownloaded", info, None),
+            MainCached(info) => ("MainCached", info, None),
+            MainProgress(info, _, _) => ("MainProgress", info, None),
+            MainRetrying(info, _, e) => ("MainRetrying", info, Some(e)),
+            MainError(info, e) => ("MainError", info, Some(e)),
+            DepAlreadyDownloaded(info) => ("DepAlreadyDownloaded", info, None),
+            DepDownloading(info) => ("DepDownloading", info, None),
+            DepDownloaded(info) => ("DepDownloaded", info, None),
+            DepCached(info) => ("DepCached", info, None),
+            DepProgress(info, _, _) => ("DepProgress", info, None),
+            DepRetrying(info, _, e) => ("DepRetrying", info, Some(e)),
+            DepError(info, e) => ("DepError", info, Some(e)),
+        };
+
+        let mut value = json!({
+            "event": name,
+            "provider": info.provider,
+            "project_id": info.project_id,
+            "file_id": info.id,
+            "display_name": info.display_name,
+            "file_name": info.file_name,
+        });
+        if let Some((received, total)) = progress {
+            value["received"] = json!(received);
+            value["total"] = json!(total);
+        }
+        if let Some(attempt) = attempt {
+            value["attempt"] = json!(attempt);
+        }
+        if let Some(e) = error {
+            value["error"] = json!({
+                "kind": e.kind(),
+                "message": e.to_string(),
+            });
+        }
+
+        self.emit(value);
+    }
+
+    /// Prints the JSON array, which is only done once the command has
+    /// succeeded so that a failed one doesn't look like an empty result.
+    pub fn finish(&self) {
+        if self.format == Format::Json {
+            println!("{}", Value::Array(self.buffer.take()));
+        }
+    }
+
+    fn emit(&self, value: Value) {
+        match self.format {
+            Format::Human => {}
+            Format::Json => self.buffer.borrow_mut().push(value),
+            Format::NdJson => println!("{}", value),
+        }
+    }
+}
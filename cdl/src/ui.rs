@@ -46,14 +46,11 @@ where
 
         for header in headers {
             tmp.push_str(&format!("{}  ", &header));
-            tmp.push_str(&format!(
-                "{}",
-                " ".repeat(if header.len() <= max_len {
-                    max_len - header.len()
-                } else {
-                    0
-                })
-            ));
+            tmp.push_str(&" ".repeat(if header.len() <= max_len {
+                max_len - header.len()
+            } else {
+                0
+            }));
         }
 
         tmp.trim_end().to_string()
@@ -85,7 +82,7 @@ where
 }
 
 pub fn parse_input(input: &str) -> Option<Vec<usize>> {
-    let foo = input
+    let parsed = input
         .trim()
         .split(' ')
         .filter_map(|s| {
@@ -107,9 +104,9 @@ pub fn parse_input(input: &str) -> Option<Vec<usize>> {
             a
         });
 
-    match foo.len() {
+    match parsed.len() {
         0 => None,
-        _ => Some(foo),
+        _ => Some(parsed),
     }
 }
 
@@ -121,6 +118,36 @@ pub fn read_input() -> io::Result<String> {
     Ok(s.trim().to_string())
 }
 
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
+
+/// A bar `width` characters wide, filled to show how much of `total` is done.
+pub fn progress_bar(done: u64, total: u64, width: usize) -> String {
+    let filled = match total {
+        0 => width,
+        _ => (width as u64 * done.min(total) / total) as usize,
+    };
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(width - filled))
+}
+
+pub fn confirm(prompt: &str) -> io::Result<bool> {
+    println!("{} [y/N]", prompt);
+    let input = read_input()?;
+    Ok(matches!(input.to_lowercase().as_str(), "y" | "yes"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,4 +162,20 @@ mod tests {
         assert_eq!(parse_input("1 3 5-6 7"), Some(vec![1, 3, 5, 6, 7]));
         assert_eq!(parse_input("1-3 1 2 3"), Some((1..=3).collect()));
     }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+
+    #[test]
+    fn bars() {
+        assert_eq!(progress_bar(0, 100, 4), "[    ]");
+        assert_eq!(progress_bar(50, 100, 4), "[##  ]");
+        assert_eq!(progress_bar(200, 100, 4), "[####]");
+        assert_eq!(progress_bar(0, 0, 4), "[####]");
+    }
 }